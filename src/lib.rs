@@ -2,19 +2,35 @@
 //!
 //! Example:
 //! ```rust
-//! let app = express::Application::new(); \
-//! app.get("/hello" , |req , res| { \
-//!     return res.status(200).json(r#"{"name":"omar"}"#)) \
-//! })
+//! use express_rs::express;
+//!
+//! let mut app = express::Application::new();
+//! app.get("/hello".to_string(), |_req, res| {
+//!     return res.status(200).json(r#"{"name":"omar"}"#.to_string());
+//! });
 //! ```
+#![allow(clippy::needless_return)]
 
 pub mod express {
+    use std::any::{Any, TypeId};
+    use std::cell::{Cell, Ref, RefCell};
     use std::collections::HashMap;
-    use std::io::Read;
-    use std::io::Write;
-    use std::net::{TcpListener, TcpStream};
+    use std::io::{BufRead, BufReader, Read};
+    use std::io::{BufWriter, Write};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+    use std::panic::{self, AssertUnwindSafe};
+    #[cfg(feature = "tokio")]
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Condvar, Mutex, Once};
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-    /// This enum define the fundmental HTTP Methods (GET, POST , PUT , PATCH , DELETE)
+    /// This enum define the fundmental HTTP Methods (GET, POST , PUT , PATCH , DELETE , HEAD , OPTIONS)
+    ///
+    /// A HEAD request without a HEAD route is answered by the GET route , without the body \
+    /// Any other method token is kept as sent , casing included , in [Method::Other]
     #[derive(Hash, Eq, PartialEq, Debug, Clone)]
     pub enum Method {
         GET,
@@ -22,17 +38,424 @@ pub mod express {
         PUT,
         PATCH,
         DELETE,
+        HEAD,
+        OPTIONS,
+        Other(String),
+    }
+
+    impl Method {
+        /// Returns the method token , as it is written in a request
+        pub fn as_str(&self) -> &str {
+            match self {
+                Method::GET => "GET",
+                Method::POST => "POST",
+                Method::PUT => "PUT",
+                Method::PATCH => "PATCH",
+                Method::DELETE => "DELETE",
+                Method::HEAD => "HEAD",
+                Method::OPTIONS => "OPTIONS",
+                Method::Other(method) => method,
+            }
+        }
     }
 
-    /// This enum is for request body parsing , it contain fundmental types (JSON , FormData , Text , Binary)
+    /// This enum is for request body parsing , it contain fundmental types (JSON , FormData , Multipart , Text , Binary)
     #[derive(Debug)]
     pub enum Body {
         JSON(String),
-        FormData(HashMap<String, String>),
+        FormData(Form),
+        Multipart(Multipart),
         Text(String),
         Binary(Vec<u8>),
     }
 
+    impl Body {
+        /// Returns the length in bytes of the body as it was received
+        pub fn len(&self) -> usize {
+            match self {
+                Body::JSON(json) => json.len(),
+                Body::FormData(form) => form.raw.len(),
+                Body::Multipart(multipart) => multipart.raw.len(),
+                Body::Text(text) => text.len(),
+                Body::Binary(bytes) => bytes.len(),
+            }
+        }
+
+        /// Returns true when the body has no bytes
+        pub fn is_empty(&self) -> bool {
+            return self.len() == 0;
+        }
+
+        /// Returns the bytes of the body as it was received
+        ///
+        /// JSON and text bodies that weren't valid UTF-8 were decoded lossily , their bytes differ from the received ones
+        pub fn as_bytes(&self) -> &[u8] {
+            match self {
+                Body::JSON(json) => json.as_bytes(),
+                Body::FormData(form) => &form.raw,
+                Body::Multipart(multipart) => &multipart.raw,
+                Body::Text(text) => text.as_bytes(),
+                Body::Binary(bytes) => bytes,
+            }
+        }
+    }
+
+    /// The hash function of the HMAC signatures checked by [Request::verify_signature]
+    #[cfg(feature = "hmac")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SignatureAlgorithm {
+        Sha256,
+        Sha384,
+        Sha512,
+    }
+
+    /// A parsed `application/x-www-form-urlencoded` body
+    ///
+    /// Values are percent-decoded , the raw bytes of the body are kept and can be read with [Form::raw] \
+    /// Fields keep the order they were submitted in , and a key can appear more than once
+    #[derive(Debug)]
+    pub struct Form {
+        fields: Vec<(String, String)>,
+        raw: Vec<u8>,
+    }
+
+    impl Form {
+        fn parse(raw: Vec<u8>) -> Form {
+            let mut fields = Vec::new();
+            for key_value in raw.split(|byte| *byte == b'&') {
+                if key_value.is_empty() {
+                    continue;
+                }
+                let (key, value) = match key_value.iter().position(|byte| *byte == b'=') {
+                    Some(index) => (&key_value[..index], &key_value[index + 1..]),
+                    None => (key_value, &[][..]),
+                };
+                fields.push((
+                    String::from_utf8_lossy(&percent_decode(key, true)).to_string(),
+                    String::from_utf8_lossy(&percent_decode(value, true)).to_string(),
+                ));
+            }
+            return Form { fields, raw };
+        }
+
+        /// Returns the decoded value of a field , the first one when the key was submitted more than once
+        pub fn get(&self, key: &str) -> Option<&String> {
+            self.fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+        }
+
+        /// Returns every decoded value of a field , in the order they were submitted
+        pub fn get_all(&self, key: &str) -> Vec<&String> {
+            self.fields
+                .iter()
+                .filter(|(name, _)| name == key)
+                .map(|(_, value)| value)
+                .collect()
+        }
+
+        /// Iterate over the fields in the order they were submitted
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/form".to_string(), |request, response| {
+        ///        if let Some(express::Body::FormData(form)) = &*request.body() {
+        ///            for (key, value) in form.iter() {
+        ///                println!("{} = {}", key, value);
+        ///            }
+        ///        }
+        ///        response.status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+            self.fields
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+        }
+
+        /// Returns the number of fields , repeated keys are counted every time
+        pub fn len(&self) -> usize {
+            self.fields.len()
+        }
+
+        /// Returns true when the form has no fields
+        pub fn is_empty(&self) -> bool {
+            self.fields.is_empty()
+        }
+
+        /// Returns the raw urlencoded bytes of the body
+        pub fn raw(&self) -> &[u8] {
+            &self.raw
+        }
+    }
+
+    /// A parsed `multipart/form-data` body , the way browsers upload files
+    ///
+    /// Parts keep the order they were submitted in , plain fields are parts without a filename
+    #[derive(Debug)]
+    pub struct Multipart {
+        parts: Vec<Part>,
+        raw: Vec<u8>,
+    }
+
+    /// One part of a [Multipart] body , an uploaded file or a plain field
+    #[derive(Debug)]
+    pub struct Part {
+        name: String,
+        filename: Option<String>,
+        content_type: Option<String>,
+        data: Vec<u8>,
+    }
+
+    impl Multipart {
+        // split the body on `--boundary` lines until the `--boundary--` terminator ,
+        // parts without a name are skipped and a malformed part ends the parsing
+        fn parse(raw: Vec<u8>, boundary: &str) -> Multipart {
+            let delimiter = format!("--{}", boundary).into_bytes();
+            let mut parts = Vec::new();
+            let mut rest = match find_bytes(&raw, &delimiter) {
+                Some(start) => &raw[start + delimiter.len()..],
+                None => &[][..],
+            };
+            let mut next_delimiter = b"\r\n".to_vec();
+            next_delimiter.extend_from_slice(&delimiter);
+            while let Some(after_line) = rest.strip_prefix(b"\r\n") {
+                let Some(header_end) = find_bytes(after_line, b"\r\n\r\n") else {
+                    break;
+                };
+                let Some(data_end) = find_bytes(&after_line[header_end + 4..], &next_delimiter)
+                else {
+                    break;
+                };
+                let headers = String::from_utf8_lossy(&after_line[..header_end]);
+                let data = &after_line[header_end + 4..header_end + 4 + data_end];
+                if let Some(part) = Part::parse(&headers, data) {
+                    parts.push(part);
+                }
+                // after the delimiter comes "\r\n" and the next part , or "--" at the end
+                rest = &after_line[header_end + 4 + data_end + next_delimiter.len()..];
+            }
+            return Multipart { parts, raw };
+        }
+
+        /// Returns the first part with the given name
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/avatar".to_string(), |request, response| {
+        ///        if let Some(express::Body::Multipart(multipart)) = &*request.body() {
+        ///            if let Some(file) = multipart.get("avatar") {
+        ///                let name = file.filename().unwrap_or("avatar.bin");
+        ///                std::fs::write(name, file.data()).unwrap();
+        ///                return response.status(201);
+        ///            }
+        ///        }
+        ///        response.status(400)
+        ///    });
+        /// ```
+        ///
+        pub fn get(&self, name: &str) -> Option<&Part> {
+            self.parts.iter().find(|part| part.name == name)
+        }
+
+        /// Returns the parts in the order they were submitted
+        pub fn parts(&self) -> &[Part] {
+            &self.parts
+        }
+
+        /// Returns the parts that are uploaded files (they have a filename)
+        pub fn files(&self) -> impl Iterator<Item = &Part> {
+            self.parts.iter().filter(|part| part.filename.is_some())
+        }
+
+        /// Returns the raw bytes of the body
+        pub fn raw(&self) -> &[u8] {
+            &self.raw
+        }
+    }
+
+    impl Part {
+        fn parse(headers: &str, data: &[u8]) -> Option<Part> {
+            let mut name = None;
+            let mut filename = None;
+            let mut content_type = None;
+            for line in headers.split("\r\n") {
+                let Some((header, value)) = line.split_once(':') else {
+                    continue;
+                };
+                if header.trim().eq_ignore_ascii_case("Content-Disposition") {
+                    for (key, value) in header_params(value) {
+                        if key.eq_ignore_ascii_case("name") {
+                            name = Some(value);
+                        } else if key.eq_ignore_ascii_case("filename") {
+                            filename = Some(value);
+                        }
+                    }
+                } else if header.trim().eq_ignore_ascii_case("Content-Type") {
+                    content_type = Some(value.trim().to_string());
+                }
+            }
+            return Some(Part {
+                name: name?,
+                filename,
+                content_type,
+                data: data.to_vec(),
+            });
+        }
+
+        /// Returns the name of the form field
+        pub fn name(&self) -> &str {
+            &self.name
+        }
+
+        /// Returns the name of the uploaded file , `None` for a plain field
+        pub fn filename(&self) -> Option<&str> {
+            self.filename.as_deref()
+        }
+
+        /// Returns the content type sent with the part , plain fields usually have none
+        pub fn content_type(&self) -> Option<&str> {
+            self.content_type.as_deref()
+        }
+
+        /// Returns the content of the part
+        pub fn data(&self) -> &[u8] {
+            &self.data
+        }
+
+        /// Returns the content of the part as text , invalid UTF-8 is replaced with U+FFFD
+        pub fn text(&self) -> String {
+            String::from_utf8_lossy(&self.data).to_string()
+        }
+    }
+
+    // the position of the first occurrence of `needle` in `haystack`
+    fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        return haystack
+            .windows(needle.len())
+            .position(|window| window == needle);
+    }
+
+    // the `key=value` parameters after the first `;` of a header value , quoted values are unquoted
+    // and may contain `;`
+    fn header_params(value: &str) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+        let mut rest = match value.split_once(';') {
+            Some((_, rest)) => rest,
+            None => return params,
+        };
+        while let Some((key, after_key)) = rest.split_once('=') {
+            let after_key = after_key.trim_start();
+            let (value, after_value) = match after_key.strip_prefix('"') {
+                Some(quoted) => match quoted.find('"') {
+                    Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                    None => (quoted, ""),
+                },
+                None => match after_key.find(';') {
+                    Some(end) => (after_key[..end].trim_end(), &after_key[end..]),
+                    None => (after_key.trim_end(), ""),
+                },
+            };
+            params.push((key.trim().to_string(), value.to_string()));
+            rest = match after_value.split_once(';') {
+                Some((_, rest)) => rest,
+                None => "",
+            };
+        }
+        return params;
+    }
+
+    // the largest request bodies the server reads , None for no limit
+    #[derive(Debug, Clone, Copy, Default)]
+    struct BodyLimits {
+        json: Option<usize>,
+        body: Option<usize>,
+    }
+
+    // decode %XX escapes (and '+' as a space when asked to) , malformed escapes are kept as they are
+    fn percent_decode(input: &[u8], plus_as_space: bool) -> Vec<u8> {
+        let mut output = Vec::with_capacity(input.len());
+        let mut index = 0;
+        while index < input.len() {
+            match input[index] {
+                b'%' if index + 2 < input.len() => {
+                    let high = (input[index + 1] as char).to_digit(16);
+                    let low = (input[index + 2] as char).to_digit(16);
+                    if let (Some(high), Some(low)) = (high, low) {
+                        output.push((high * 16 + low) as u8);
+                        index += 3;
+                        continue;
+                    }
+                    output.push(b'%');
+                }
+                b'+' if plus_as_space => output.push(b' '),
+                byte => output.push(byte),
+            }
+            index += 1;
+        }
+        return output;
+    }
+
+    // decode a hex string (either case) , None when it has an odd length or another character
+    #[cfg(feature = "hmac")]
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        return hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| {
+                let high = (pair[0] as char).to_digit(16)?;
+                let low = (pair[1] as char).to_digit(16)?;
+                Some((high * 16 + low) as u8)
+            })
+            .collect();
+    }
+
+    // decode the chunked body at the start of `bytes` , returning it and the number of bytes it used ,
+    // fails as soon as the decoded body is larger than `limit`
+    fn read_chunked_body(
+        bytes: &[u8],
+        limit: Option<usize>,
+    ) -> Result<(Vec<u8>, usize), ParseError> {
+        let mut remaining = bytes;
+        match chunked::decode_limited(&mut remaining, limit) {
+            Ok(body) => return Ok((body, bytes.len() - remaining.len())),
+            Err(chunked::ChunkError::TooLarge(limit)) => {
+                return Err(ParseError::BodyTooLarge(limit));
+            }
+            Err(chunked::ChunkError::UnexpectedEof) => {}
+            Err(_) => return Err(ParseError::InvalidChunkedBody),
+        }
+        // the framing (sizes , extensions) can't be larger than the body it carries ,
+        // so a client can't fill the buffer with it while the body stays under the limit
+        if let Some(limit) = limit
+            && bytes.len() > limit.saturating_mul(2).saturating_add(MAX_HEADER_BYTES)
+        {
+            return Err(ParseError::BodyTooLarge(limit));
+        }
+        return Err(ParseError::Incomplete);
+    }
+
+    // the media type of a Content-Type value , lowercased and without its parameters
+    fn essence(content_type: &str) -> String {
+        let media_type = content_type.split(';').next().unwrap_or_default();
+        return media_type.trim().to_ascii_lowercase();
+    }
+
+    // percent-decode a path segment or a query string name or value into text
+    fn decode_component(component: &str, plus_as_space: bool) -> String {
+        return String::from_utf8_lossy(&percent_decode(component.as_bytes(), plus_as_space))
+            .to_string();
+    }
+
     /// Request struct is responsible for incoming request parsing
     #[derive(Debug)]
     pub struct Request {
@@ -42,15 +465,212 @@ pub mod express {
         pub route: String,
         /// The headers of the request
         pub headers: HashMap<String, String>,
-        /// The body of the request , an instance of [Body] Enum
-        pub body: Option<Body>,
+        /// Typed values attached to the request (by middleware for example) , an instance of [Extensions]
+        pub extensions: Extensions,
         params: Option<HashMap<String, String>>,
         search_params: Option<HashMap<String, String>>,
+        cookies: Option<HashMap<String, String>>,
+        connection_index: usize,
+        // the protocol from the request line , `HTTP/1.1` for example
+        version: String,
+        trust_proxy: bool,
+        secure: bool,
+        valid_header: bool,
+        // set when the application tracks request IDs , see `Application::request_id_header`
+        request_id: Option<String>,
+        // behind a RefCell so a handler holding `&Request` can still take the body out
+        body: RefCell<Option<Body>>,
+    }
+
+    /// A map of values keyed by their type , used to pass data along with a [Request]
+    ///
+    /// # Example:
+    /// ```rust
+    /// use express_rs::express::Extensions;
+    ///
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut extensions = Extensions::new();
+    /// extensions.insert(User { name: "omar".to_string() });
+    /// assert_eq!(extensions.get::<User>().unwrap().name, "omar");
+    /// ```
+    #[derive(Default)]
+    pub struct Extensions {
+        map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    }
+
+    impl Extensions {
+        pub fn new() -> Extensions {
+            return Extensions {
+                map: HashMap::new(),
+            };
+        }
+
+        /// Insert a value , returning the previous value of the same type if there was one
+        pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+            self.map
+                .insert(TypeId::of::<T>(), Box::new(value))
+                .and_then(|old| old.downcast().ok())
+                .map(|old| *old)
+        }
+
+        /// Get a reference to the value of type `T`
+        pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+            self.map
+                .get(&TypeId::of::<T>())
+                .and_then(|value| value.downcast_ref())
+        }
+
+        /// Get a mutable reference to the value of type `T`
+        pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+            self.map
+                .get_mut(&TypeId::of::<T>())
+                .and_then(|value| value.downcast_mut())
+        }
+
+        /// Remove the value of type `T` and return it
+        pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+            self.map
+                .remove(&TypeId::of::<T>())
+                .and_then(|value| value.downcast().ok())
+                .map(|value| *value)
+        }
+    }
+
+    impl std::fmt::Debug for Extensions {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("Extensions")
+                .field("len", &self.map.len())
+                .finish()
+        }
     }
 
     impl Request {
-        fn new(stream: &mut TcpStream) -> Request {
-            let (v, left_over_of_body) = read_header(stream);
+        fn new(
+            stream: &mut Stream,
+            buffer: &mut Vec<u8>,
+            limits: BodyLimits,
+            timeout: Duration,
+        ) -> Result<Request, RequestError> {
+            let mut temp = [0u8; 4096];
+            // every read waits at most until the whole request is due
+            let deadline = Instant::now() + timeout;
+
+            // `buffer` starts with the bytes left over by the previous request on the connection ,
+            // the bytes past this request stay in it for the next one
+            loop {
+                if let Some(request) = Request::take(buffer, limits)? {
+                    return Ok(request);
+                }
+
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    let error = std::io::Error::from(std::io::ErrorKind::TimedOut);
+                    return Err(RequestError::Io(error));
+                }
+                stream
+                    .set_read_timeout(Some(remaining))
+                    .map_err(RequestError::Io)?;
+                let n = match stream.read(&mut temp) {
+                    Ok(n) => n,
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(error) => return Err(RequestError::Io(error)),
+                };
+                if n == 0 {
+                    return Err(RequestError::IncompleteHeaders);
+                }
+                buffer.extend_from_slice(&temp[..n]);
+            }
+        }
+
+        // `Request::new` for a tokio socket
+        #[cfg(feature = "tokio")]
+        async fn read_async(
+            socket: &mut tokio::net::TcpStream,
+            buffer: &mut Vec<u8>,
+            limits: BodyLimits,
+        ) -> Result<Request, RequestError> {
+            use tokio::io::AsyncReadExt;
+
+            let mut temp = [0u8; 4096];
+            loop {
+                if let Some(request) = Request::take(buffer, limits)? {
+                    return Ok(request);
+                }
+
+                let n = socket.read(&mut temp).await.map_err(RequestError::Io)?;
+                if n == 0 {
+                    return Err(RequestError::IncompleteHeaders);
+                }
+                buffer.extend_from_slice(&temp[..n]);
+            }
+        }
+
+        // take the request at the start of `buffer` out of it , None while it isn't complete
+        fn take(buffer: &mut Vec<u8>, limits: BodyLimits) -> Result<Option<Request>, RequestError> {
+            match Request::parse_limited(buffer, limits) {
+                Ok((request, consumed)) => {
+                    buffer.drain(..consumed);
+                    return Ok(Some(request));
+                }
+                Err(ParseError::Incomplete) => return Ok(None),
+                Err(error) => return Err(RequestError::from(error)),
+            }
+        }
+
+        /// Parse a request (header and body) from the start of `bytes` , without any socket
+        ///
+        /// On success it returns the request and the number of bytes it used , anything after
+        /// that belongs to the next request \
+        /// The body is delimited by `Content-Length` or by the chunked `Transfer-Encoding` \
+        /// [ParseError::Incomplete] means more bytes are needed
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::{Body, Method, ParseError, Request};
+        ///
+        /// let raw = b"POST /users HTTP/1.1\r\nContent-Length: 2\r\nContent-Type: text/plain\r\n\r\nhi";
+        /// let (request, consumed) = Request::parse(raw).unwrap();
+        /// assert_eq!(request.method, Method::POST);
+        /// assert_eq!(consumed, raw.len());
+        ///
+        /// let raw = b"POST /notes HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n2\r\nhi\r\n3\r\n!!!\r\n0\r\n\r\n";
+        /// let (request, consumed) = Request::parse(raw).unwrap();
+        /// assert!(matches!(&*request.body(), Some(Body::Text(text)) if text == "hi!!!"));
+        /// assert_eq!(consumed, raw.len());
+        ///
+        /// assert!(matches!(Request::parse(b"GET / HTTP/1.1\r\n"), Err(ParseError::Incomplete)));
+        /// ```
+        pub fn parse(bytes: &[u8]) -> Result<(Request, usize), ParseError> {
+            return Request::parse_limited(bytes, BodyLimits::default());
+        }
+
+        // `parse` , also failing as soon as the headers announce a body larger than `limits`
+        // so the server doesn't wait for (or read) the rest of it
+        fn parse_limited(bytes: &[u8], limits: BodyLimits) -> Result<(Request, usize), ParseError> {
+            let header_end = match bytes.windows(4).position(|w| w == b"\r\n\r\n") {
+                Some(pos) => pos + 4,
+                None if bytes.len() > MAX_HEADER_BYTES => return Err(ParseError::HeadersTooLarge),
+                None => return Err(ParseError::Incomplete),
+            };
+            if header_end > MAX_HEADER_BYTES {
+                return Err(ParseError::HeadersTooLarge);
+            }
+            let header_bytes = &bytes[..header_end];
+
+            let valid_header = std::str::from_utf8(header_bytes).is_ok();
+            let header = String::from_utf8_lossy(header_bytes).to_string();
+            let v: Vec<_> = header
+                .split("\n")
+                .map(|line| line.trim().to_owned())
+                .filter(|line| !line.is_empty())
+                .collect();
+
+            if v.is_empty() {
+                return Err(ParseError::MalformedStartLine);
+            }
 
             let first_line: Vec<_> = v[0]
                 .split_ascii_whitespace()
@@ -59,7 +679,11 @@ pub mod express {
                 .collect();
 
             if first_line.len() != 3 {
-                panic!("The Header is invalid (first line)")
+                return Err(ParseError::MalformedStartLine);
+            }
+            // the HTTP/2 prior knowledge preface , `Upgrade: h2c` requests are just served as HTTP/1.1
+            if first_line == ["PRI", "*", "HTTP/2.0"] {
+                return Err(ParseError::Http2Preface);
             }
 
             let method = match first_line[0] {
@@ -68,8 +692,14 @@ pub mod express {
                 "PUT" => Method::PUT,
                 "PATCH" => Method::PATCH,
                 "DELETE" => Method::DELETE,
-                _ => panic!("Invalid Method"),
+                "HEAD" => Method::HEAD,
+                "OPTIONS" => Method::OPTIONS,
+                other if other.bytes().all(is_token_byte) => Method::Other(other.to_string()),
+                _ => return Err(ParseError::UnknownMethod),
             };
+            if first_line[1].len() > MAX_URI_LENGTH {
+                return Err(ParseError::UriTooLong);
+            }
             let route = first_line[1].to_string();
             let mut hashmap = HashMap::new();
             for i in &v[1..] {
@@ -87,363 +717,7148 @@ pub mod express {
                 }
             }
 
-            let body: Option<Body>;
+            // the body is always consumed so the bytes of a pipelined request that follows stay intact
+            let content_length = match header_value(&hashmap, "Content-Length") {
+                // only digits , `usize::from_str` would also take a sign
+                Some(value) if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                    match value.parse::<usize>() {
+                        Ok(length) => length,
+                        Err(_) => return Err(ParseError::InvalidContentLength),
+                    }
+                }
+                Some(_) => return Err(ParseError::InvalidContentLength),
+                None => 0,
+            };
+            let content_type = header_value(&hashmap, "Content-Type").map(|value| essence(value));
+            let is_json = content_type.as_deref() == Some("application/json");
+            let limit = match (is_json, limits.json, limits.body) {
+                (true, Some(json), Some(body)) => Some(json.min(body)),
+                (true, Some(json), None) => Some(json),
+                (_, _, body) => body,
+            };
 
-            if let (Some(content_length), Some(content_type)) =
-                (hashmap.get("Content-Length"), hashmap.get("Content-Type"))
-            {
-                let body_bytes =
-                    read_body(stream, content_length.parse().unwrap(), left_over_of_body);
-                body = match content_type.as_str() {
+            let transfer_encoding = header_value(&hashmap, "Transfer-Encoding");
+            let has_body =
+                transfer_encoding.is_some() || header_value(&hashmap, "Content-Length").is_some();
+            let (body_bytes, request_end) = match transfer_encoding {
+                // a length and a transfer coding could be read differently by a proxy in front ,
+                // refuse the request rather than pick one
+                Some(_) if header_value(&hashmap, "Content-Length").is_some() => {
+                    return Err(ParseError::InvalidContentLength);
+                }
+                Some(coding) => {
+                    let last = coding.rsplit(',').next().unwrap_or("").trim();
+                    if !last.eq_ignore_ascii_case("chunked") {
+                        return Err(ParseError::UnsupportedTransferEncoding);
+                    }
+                    let (body, length) = read_chunked_body(&bytes[header_end..], limit)?;
+                    (body, header_end + length)
+                }
+                None => {
+                    if let Some(limit) = limit
+                        && content_length > limit
+                    {
+                        return Err(ParseError::BodyTooLarge(limit));
+                    }
+                    let request_end = header_end + content_length;
+                    if bytes.len() < request_end {
+                        return Err(ParseError::Incomplete);
+                    }
+                    (bytes[header_end..request_end].to_vec(), request_end)
+                }
+            };
+
+            let body: Option<Body> = if has_body {
+                // a body without a Content-Type is kept as bytes
+                let content_type = content_type.unwrap_or_default();
+                match content_type.as_str() {
                     "application/json" => {
                         Some(Body::JSON(String::from_utf8_lossy(&body_bytes).to_string()))
                     }
                     "application/x-www-form-urlencoded" => {
-                        let mut map = HashMap::new();
-                        let string = String::from_utf8_lossy(&body_bytes).to_string();
-                        let vec: Vec<&str> = string.split("&").collect();
-                        for key_value in vec {
-                            let (key, value) = key_value.split_once("=").unwrap();
-                            map.insert(key.to_string(), value.to_string());
-                        }
-                        Some(Body::FormData(map))
+                        Some(Body::FormData(Form::parse(body_bytes)))
                     }
                     "text/plain" => {
                         Some(Body::Text(String::from_utf8_lossy(&body_bytes).to_string()))
                     }
-                    x if !x.is_empty() => Some(Body::Binary(body_bytes)),
+                    "multipart/form-data" => {
+                        let boundary = header_value(&hashmap, "Content-Type").and_then(|value| {
+                            header_params(value)
+                                .into_iter()
+                                .find(|(key, _)| key.eq_ignore_ascii_case("boundary"))
+                        });
+                        match boundary {
+                            Some((_, boundary)) if !boundary.is_empty() => {
+                                Some(Body::Multipart(Multipart::parse(body_bytes, &boundary)))
+                            }
+                            _ => Some(Body::Binary(body_bytes)),
+                        }
+                    }
+                    x if !x.is_empty() || !body_bytes.is_empty() => Some(Body::Binary(body_bytes)),
                     _ => None,
-                };
+                }
             } else {
-                body = None
-            }
+                None
+            };
 
-            return Request {
+            let cookies = header_value(&hashmap, "Cookie").map(|value| parse_cookies(value));
+            let request = Request {
                 method,
                 route,
                 headers: hashmap,
-                body,
+                body: RefCell::new(body),
+                extensions: Extensions::new(),
                 params: None,
                 search_params: None,
+                cookies,
+                connection_index: 0,
+                version: first_line[2].to_string(),
+                trust_proxy: false,
+                secure: false,
+                valid_header,
+                request_id: None,
             };
+            return Ok((request, request_end));
         }
 
-        /// This function is used to get a param from the request \
-        /// It uses the dynamic route defined in the method definition
+        /// The body of the request , an instance of [Body] Enum
+        ///
+        /// It is `None` when the request has no body or after [Request::take_body] took it ,
+        /// a body sent without a `Content-Type` is a [Body::Binary] \
+        /// Drop the returned reference before calling [Request::take_body]
         ///
         /// # Example:
         /// ```rust
-        ///    app.get("/user/:id", |request, response| { \
-        ///        let id = request.get_param("id").unwrap(); \
-        ///         response.status(200).json(format!(r#"{"id": {}}"# , id.parse().unwrap())); \
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/users".to_string(), |request, response| {
+        ///        match &*request.body() {
+        ///            Some(express::Body::JSON(json)) => response.status(201).json(json.clone()),
+        ///            _ => response.status(400),
+        ///        }
         ///    });
         /// ```
         ///
-        pub fn get_param(&self, key: &str) -> Option<String> {
-            match &self.params {
-                Some(map) => match map.get(key) {
-                    Some(value) => Some(value.clone()),
-                    None => None,
-                },
-                None => None,
-            }
+        pub fn body(&self) -> Ref<'_, Option<Body>> {
+            return self.body.borrow();
         }
-        /// This function is used to get a search_param from the request
+
+        /// Move the body out of the request , without cloning it
+        ///
+        /// Later calls (and [Request::body]) return `None`
         ///
         /// # Example:
         /// ```rust
-        ///    app.get("/products", |request, response| {
-        ///        let max_price = request.get_search_param("max_price").unwrap();
-        ///        response.status(200);
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/upload".to_string(), |request, response| {
+        ///        if let Some(express::Body::Binary(bytes)) = request.take_body() {
+        ///            std::fs::write("upload.bin", bytes).unwrap();
+        ///        }
+        ///        response.status(201)
         ///    });
         /// ```
         ///
-        pub fn get_search_param(&self, key: &str) -> Option<String> {
-            match &self.search_params {
-                Some(map) => match map.get(key) {
-                    Some(value) => Some(value.clone()),
-                    None => None,
-                },
-                None => None,
-            }
-        }
-    }
-    fn read_body(stream: &mut TcpStream, content_length: usize, left_over: Vec<u8>) -> Vec<u8> {
-        let mut buf = left_over;
-        let mut rest = vec![0u8; content_length - buf.len()];
-        let _ = stream.read_exact(&mut rest);
-        buf.extend_from_slice(&rest);
-        return buf;
-    }
-
-    fn read_header(stream: &mut TcpStream) -> (Vec<String>, Vec<u8>) {
-        let mut buf: Vec<_> = Vec::new();
-        let mut temp = [0u8; 512];
-
-        loop {
-            let n = stream.read(&mut temp).unwrap();
-            if n == 0 {
-                break;
-            }
-            buf.extend_from_slice(&temp[..n]);
-
-            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
-                let header_end = 4 + pos;
-                let header_bytes = buf[..header_end].to_vec();
-                let left_over = buf[header_end..].to_vec();
-
-                let header = String::from_utf8_lossy(&header_bytes).to_string();
-                let vec: Vec<_> = header
-                    .split("\n")
-                    .map(|line| line.trim().to_owned())
-                    .filter(|line| !line.is_empty())
-                    .collect();
-
-                return (vec, left_over);
-            }
+        pub fn take_body(&self) -> Option<Body> {
+            return self.body.borrow_mut().take();
         }
 
-        panic!("Connection Stopped before finishing")
-    }
-
-
-    /// The Response struct is used to send a response to the client
-    /// It can be custom
-    pub struct Response {
-        status: i32,
-        content_type: Option<String>,
-        content_length: Option<i32>,
-        body: String,
-    }
-
-    impl Response {
-        fn new() -> Response {
-            Response {
-                status: 200,
-                content_length: None,
-                content_type: None,
-                body: "".to_string(),
-            }
-        }
-        /// A function to set the status code of the response
+        /// Returns the value of a field of a `application/x-www-form-urlencoded` body ,
+        /// the first one when the key was submitted more than once
         ///
-        /// It returns the response object , so it can be chained
+        /// `None` when the field is missing or the body isn't a form
         ///
         /// # Example:
         /// ```rust
-        ///    app.get("/hello", |request, response| {
-        ///        response.status(200);
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/login".to_string(), |request, response| {
+        ///        match request.form_value("username") {
+        ///            Some(username) => response.status(200).html(username),
+        ///            None => response.status(400),
+        ///        }
         ///    });
         /// ```
         ///
-        pub fn status(mut self, code: i32) -> Self {
-            self.status = code;
-            return self;
+        pub fn form_value(&self, key: &str) -> Option<String> {
+            match &*self.body() {
+                Some(Body::FormData(form)) => form.get(key).cloned(),
+                _ => None,
+            }
         }
-        /// A function to set the body of the response to HTML
+
+        /// Returns every value of a field of a `application/x-www-form-urlencoded` body , in the order they were submitted
         ///
-        /// It returns the response object , so it can be chained
+        /// Empty when the field is missing or the body isn't a form
         ///
         /// # Example:
         /// ```rust
-        ///    app.get("/hello", |request, response| {
-        ///        response.status(200).html("<h1>Hello World</h1>".to_string());
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/pizza".to_string(), |request, response| {
+        ///        // ["cheese", "olives"] for "topping=cheese&topping=olives"
+        ///        let toppings = request.form_values("topping");
+        ///        response.status(200).html(toppings.join(", "))
         ///    });
         /// ```
         ///
-        pub fn html(mut self, html: String) -> Self {
-            self.content_type = Some("text/html".to_string());
-            self.content_length = Some(html.len() as i32);
-            self.body = html;
-            return self;
+        pub fn form_values(&self, key: &str) -> Vec<String> {
+            match &*self.body() {
+                Some(Body::FormData(form)) => form.get_all(key).into_iter().cloned().collect(),
+                _ => Vec::new(),
+            }
         }
-        /// A function to set the body of the response to JSON
+
+        /// This function is used to get a param from the request \
+        /// It uses the dynamic route defined in the method definition \
+        /// The value is percent-decoded , `/user/omar%20emad` gives `omar emad` \
+        /// A wildcard segment like `/files/*path` captures the rest of the path , `a/b/c.txt` for `/files/a/b/c.txt`
         ///
-        /// It returns the response object , so it can be chained
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/user/:id".to_string(), |request, response| {
+        ///        let id = request.get_param("id").unwrap();
+        ///        response.status(200).json(format!(r#"{{"id": "{}"}}"#, id))
+        ///    });
+        /// ```
+        ///
+        pub fn get_param(&self, key: &str) -> Option<String> {
+            match &self.params {
+                Some(map) => map.get(key).cloned(),
+                None => None,
+            }
+        }
+        /// This function is used to get a search_param from the request
+        ///
+        /// Names and values are percent-decoded and `+` is a space
         ///
         /// # Example:
         /// ```rust
-        ///    app.get("/hello", |request, response| {
-        ///        response.status(200).json(r#"{"message": "Hello World"}"#);
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/products".to_string(), |request, response| {
+        ///        let max_price = request.get_search_param("max_price").unwrap();
+        ///        response.status(200)
         ///    });
         /// ```
         ///
-        pub fn json(mut self, json: String) -> Self {
-            self.content_type = Some("application/json".to_string());
-            self.content_length = Some(json.len() as i32);
-            self.body = json;
-            return self;
+        pub fn get_search_param(&self, key: &str) -> Option<String> {
+            match &self.search_params {
+                Some(map) => map.get(key).cloned(),
+                None => None,
+            }
         }
-        fn send(&mut self, stream: &mut TcpStream) {
-            let status_line = format!("HTTP/1.1 {}", self.status);
-            println!("");
-            if let (Some(content_len), Some(content_type)) =
-                (&self.content_length, &self.content_type)
-            {
-                let response = format!(
-                    "{status_line}\r\nContent-Length: {}\r\nContent-Type: {}\r\n\r\n{}",
-                    content_len, content_type, self.body
-                );
-                stream.write_all(response.as_bytes()).unwrap();
-            } else {
-                let response = format!("{status_line}\r\n\r\n");
-                stream.write_all(response.as_bytes()).unwrap();
+
+        /// Returns every param captured from the dynamic route , `None` when the route has no params
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/user/:id/:tab".to_string(), |request, response| {
+        ///        if let Some(params) = request.params() {
+        ///            for (name, value) in params {
+        ///                println!("{} = {}", name, value);
+        ///            }
+        ///        }
+        ///        response.status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn params(&self) -> Option<&HashMap<String, String>> {
+            return self.params.as_ref();
+        }
+
+        /// Returns every search_param of the request , `None` when the route has no query string
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/products".to_string(), |request, response| {
+        ///        let filters: Vec<String> = request
+        ///            .search_params()
+        ///            .map(|params| params.keys().cloned().collect())
+        ///            .unwrap_or_default();
+        ///        response.status(200).text(filters.join(", "))
+        ///    });
+        /// ```
+        ///
+        pub fn search_params(&self) -> Option<&HashMap<String, String>> {
+            return self.search_params.as_ref();
+        }
+
+        /// This function is used to get a cookie sent in the `Cookie` header of the request
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/profile".to_string(), |request, response| {
+        ///        // "abc" for "Cookie: theme=dark; session=abc"
+        ///        match request.get_cookie("session") {
+        ///            Some(session) => response.status(200),
+        ///            None => response.status(401),
+        ///        }
+        ///    });
+        /// ```
+        ///
+        pub fn get_cookie(&self, name: &str) -> Option<String> {
+            match &self.cookies {
+                Some(map) => map.get(name).cloned(),
+                None => None,
             }
         }
-    }
 
-    #[derive(Debug)]
-    enum RouteSegment {
-        Static(String),
-        Dynamic(String),
-    }
+        /// Returns the number of params captured from the dynamic route
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/a/:x/:y".to_string(), |request, response| {
+        ///        // 2 for "/a/1/2?p=1&q=2"
+        ///        let count = request.param_count();
+        ///        response.status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn param_count(&self) -> usize {
+            return self.params.as_ref().map_or(0, |map| map.len());
+        }
 
-    type RouteFunction = dyn Fn(&Request, Response) -> Response + 'static;
+        /// Returns the number of distinct search params in the query string
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/a/:x/:y".to_string(), |request, response| {
+        ///        // 2 for "/a/1/2?p=1&q=2"
+        ///        if request.search_param_count() > 10 {
+        ///            return response.status(400);
+        ///        }
+        ///        response.status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn search_param_count(&self) -> usize {
+            return self.search_params.as_ref().map_or(0, |map| map.len());
+        }
 
-    /// The Application struct is responsible for handling incoming requests and routing them to the appropriate handler function
-    pub struct Application {
-        static_methods: HashMap<(Method, String), Box<RouteFunction>>,
-        dynamic_methods: Vec<(Method, Vec<RouteSegment>, Box<RouteFunction>)>,
-    }
+        /// Returns true when the request was sent with `X-Requested-With: XMLHttpRequest`
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/items".to_string(), |request, response| {
+        ///        if request.is_ajax() {
+        ///            return response.status(200).json("[]".to_string());
+        ///        }
+        ///        response.status(200).html("<ul></ul>".to_string())
+        ///    });
+        /// ```
+        ///
+        pub fn is_ajax(&self) -> bool {
+            match self.find_header("X-Requested-With") {
+                Some(value) => value.eq_ignore_ascii_case("XMLHttpRequest"),
+                None => false,
+            }
+        }
 
-    impl Application {
-        pub fn get<F>(&mut self, route: String, function: F)
-        where
-            F: Fn(&Request, Response) -> Response + 'static,
-        {
-            self.add_new_route(route, Method::GET, Box::new(function));
+        /// Returns the method exactly as the client sent it , `"MkCol"` stays `"MkCol"`
+        pub fn method_str(&self) -> &str {
+            return self.method.as_str();
         }
-        pub fn post<F>(&mut self, route: String, function: F)
-        where
-            F: Fn(&Request, Response) -> Response + 'static,
-        {
-            self.add_new_route(route, Method::POST, Box::new(function));
+
+        /// Returns how many requests were served on this connection before this one , 0 for the first request
+        pub fn connection_request_index(&self) -> usize {
+            return self.connection_index;
         }
-        pub fn put<F>(&mut self, route: String, function: F)
-        where
-            F: Fn(&Request, Response) -> Response + 'static,
-        {
-            self.add_new_route(route, Method::PUT, Box::new(function));
+
+        /// Returns the value of a header (the name is case insensitive) , or `default` when it wasn't sent
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/".to_string(), |request, response| {
+        ///        let language = request.header_or("Accept-Language", "en");
+        ///        response.status(200).html(language)
+        ///    });
+        /// ```
+        ///
+        pub fn header_or(&self, name: &str, default: &str) -> String {
+            match self.find_header(name) {
+                Some(value) => value.clone(),
+                None => default.to_string(),
+            }
         }
-        pub fn patch<F>(&mut self, route: String, function: F)
-        where
-            F: Fn(&Request, Response) -> Response + 'static,
-        {
-            self.add_new_route(route, Method::PATCH, Box::new(function));
+
+        /// Returns the value of a header parsed as an integer (the name is case insensitive)
+        ///
+        /// `None` when the header wasn't sent or isn't an integer , it never panics
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::Request;
+        ///
+        /// let (request, _) = Request::parse(b"GET / HTTP/1.1\r\nRetry-After: 120\r\nX-Limit: lots\r\n\r\n").unwrap();
+        /// assert_eq!(request.header_int("retry-after"), Some(120));
+        /// assert_eq!(request.header_int("X-Limit"), None);
+        /// assert_eq!(request.header_int("X-Missing"), None);
+        /// ```
+        pub fn header_int(&self, name: &str) -> Option<i64> {
+            return self.find_header(name)?.trim().parse().ok();
         }
-        pub fn delete<F>(&mut self, route: String, function: F)
-        where
-            F: Fn(&Request, Response) -> Response + 'static,
-        {
-            self.add_new_route(route, Method::DELETE, Box::new(function));
+
+        /// Returns the value of the `Host` header , including the port when one was sent
+        pub fn host(&self) -> Option<&str> {
+            self.find_header("Host").map(|value| value.trim())
         }
-    }
 
-    impl Application {
-        // create a new application
-        pub fn new() -> Application {
-            return Application {
-                static_methods: HashMap::new(),
-                dynamic_methods: Vec::new(),
-            };
+        /// Returns the `Host` header without the port
+        ///
+        /// IPv6 literals keep their brackets , `[::1]:8080` gives `[::1]`
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/".to_string(), |request, response| {
+        ///        // "example.com" for "Host: example.com:8080"
+        ///        let hostname = request.hostname().unwrap_or("localhost").to_string();
+        ///        response.status(200).html(hostname)
+        ///    });
+        /// ```
+        ///
+        pub fn hostname(&self) -> Option<&str> {
+            let host = self.host()?;
+            if host.starts_with('[') {
+                return match host.find(']') {
+                    Some(end) => Some(&host[..=end]),
+                    None => Some(host),
+                };
+            }
+            match host.split_once(':') {
+                Some((hostname, _port)) => Some(hostname),
+                None => Some(host),
+            }
         }
 
-        // start the server , takes a port as argument
-        pub fn listen(&mut self, port: i32) {
-            let listener: TcpListener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap();
+        /// Returns the scheme used by the client , `"http"` or `"https"`
+        ///
+        /// When the application trusts a proxy ([Application::trust_proxy]) the `X-Forwarded-Proto` header is used
+        pub fn scheme(&self) -> &str {
+            if self.secure {
+                return "https";
+            }
+            if let (true, Some(proto)) = (self.trust_proxy, self.find_header("X-Forwarded-Proto")) {
+                let proto = proto.split(',').next().unwrap_or("").trim();
+                if proto.eq_ignore_ascii_case("https") {
+                    return "https";
+                }
+            }
+            return "http";
+        }
 
-            println!("Started server on port {}", port);
+        /// Returns true when the request was made over HTTPS (see [Request::scheme])
+        pub fn is_secure(&self) -> bool {
+            return self.scheme() == "https";
+        }
 
-            for stream in listener.incoming() {
-                let mut stream = stream.unwrap();
-                let mut request = Request::new(&mut stream);
+        /// Returns the ID of the request , `None` unless [Application::request_id_header] is set
+        ///
+        /// It is the ID the client sent in the configured header , or a generated one
+        pub fn request_id(&self) -> Option<&str> {
+            self.request_id.as_deref()
+        }
 
-                self.execute_route(
-                    request.route.to_string(),
-                    request.method.clone(),
-                    &mut request,
-                    Response::new(),
-                    &mut stream,
-                );
+        /// Rebuilds the absolute URL of the request from the scheme , the `Host` header , the path and the query
+        ///
+        /// When the application trusts a proxy the `X-Forwarded-Host` header is preferred ,
+        /// without any host the relative route is returned
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/search".to_string(), |request, response| {
+        ///        // "http://localhost/search?q=1"
+        ///        let url = request.full_url();
+        ///        response.status(200).html(url)
+        ///    });
+        /// ```
+        ///
+        pub fn full_url(&self) -> String {
+            let mut host = None;
+            if self.trust_proxy {
+                host = self
+                    .find_header("X-Forwarded-Host")
+                    .map(|value| value.split(',').next().unwrap_or("").trim());
+            }
+            if host.is_none() {
+                host = self.find_header("Host").map(|value| value.trim());
+            }
+            match host {
+                Some(host) if !host.is_empty() => {
+                    format!("{}://{}{}", self.scheme(), host, self.route)
+                }
+                _ => self.route.clone(),
             }
         }
 
-        fn execute_route(
+        /// Returns the media type of the `Content-Type` header , lowercased and without its parameters
+        ///
+        /// The body is classified with it , so `Application/JSON; charset=utf-8` is still a JSON body
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::{Body, Request};
+        ///
+        /// let raw = b"POST / HTTP/1.1\r\nContent-Type: Application/JSON; charset=utf-8\r\nContent-Length: 2\r\n\r\n{}";
+        /// let (request, _) = Request::parse(raw).unwrap();
+        /// assert_eq!(request.content_type_essence(), Some("application/json".to_string()));
+        /// assert!(matches!(&*request.body(), Some(Body::JSON(_))));
+        ///
+        /// let raw = b"POST / HTTP/1.1\r\nContent-Type: TEXT/plain;charset=\"utf-8\"\r\nContent-Length: 2\r\n\r\nhi";
+        /// let (request, _) = Request::parse(raw).unwrap();
+        /// assert_eq!(request.content_type_essence(), Some("text/plain".to_string()));
+        /// assert!(matches!(&*request.body(), Some(Body::Text(_))));
+        /// ```
+        pub fn content_type_essence(&self) -> Option<String> {
+            return self.find_header("Content-Type").map(|value| essence(value));
+        }
+
+        /// Check the HMAC signature of the body sent in the `header_name` header , for webhooks (requires the `hmac` feature)
+        ///
+        /// The header holds the hex encoded digest , optionally prefixed by the algorithm name (`sha256=...` like GitHub) \
+        /// The comparison takes constant time , it returns false when the header is missing or malformed \
+        /// Call it before [Request::take_body] , an empty body is checked otherwise
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::{Request, SignatureAlgorithm};
+        ///
+        /// let signature = "sha256=4bde3c96f2f18cdfff5e0da4d173ea93a3ea438af8b417aa8bb2a9d47d27c8a6";
+        /// let raw = format!(
+        ///     "POST /webhook HTTP/1.1\r\nX-Hub-Signature-256: {}\r\nContent-Type: application/json\r\nContent-Length: 19\r\n\r\n{}",
+        ///     signature, r#"{"action":"opened"}"#
+        /// );
+        /// let (request, _) = Request::parse(raw.as_bytes()).unwrap();
+        /// assert!(request.verify_signature("X-Hub-Signature-256", b"It is a secret", SignatureAlgorithm::Sha256));
+        ///
+        /// let tampered = raw.replace("opened", "closed");
+        /// let (request, _) = Request::parse(tampered.as_bytes()).unwrap();
+        /// assert!(!request.verify_signature("X-Hub-Signature-256", b"It is a secret", SignatureAlgorithm::Sha256));
+        /// ```
+        #[cfg(feature = "hmac")]
+        pub fn verify_signature(
             &self,
-            route: String,
-            method: Method,
-            request: &mut Request,
-            response: Response,
-            stream: &mut TcpStream,
-        ) {
-            let mut filtered_route = route;
-            if filtered_route.contains('?') {
-                let (route, query) = filtered_route.split_once('?').unwrap();
-                let mut search_params_map = HashMap::new();
-                for param in query.split('&') {
-                    if let Some((name, value)) = param.split_once('=') {
-                        search_params_map.insert(name.to_string(), value.to_string());
-                    }
-                }
-                request.search_params = Some(search_params_map);
-                filtered_route = route.to_string();
+            header_name: &str,
+            secret: &[u8],
+            algorithm: SignatureAlgorithm,
+        ) -> bool {
+            use ring::hmac;
+
+            let Some(header) = self.find_header(header_name) else {
+                return false;
+            };
+            let header = header.trim();
+            let digest = header.split_once('=').map_or(header, |(_, digest)| digest);
+            let Some(tag) = decode_hex(digest) else {
+                return false;
+            };
+            let algorithm = match algorithm {
+                SignatureAlgorithm::Sha256 => hmac::HMAC_SHA256,
+                SignatureAlgorithm::Sha384 => hmac::HMAC_SHA384,
+                SignatureAlgorithm::Sha512 => hmac::HMAC_SHA512,
+            };
+            let key = hmac::Key::new(algorithm, secret);
+            let body = self.body();
+            let bytes = body.as_ref().map_or(&[][..], |body| body.as_bytes());
+            return hmac::verify(&key, bytes, &tag).is_ok();
+        }
+
+        /// Returns the body size declared by the `Content-Length` header
+        ///
+        /// Returns `None` when the body is sent with `Transfer-Encoding` (chunked) or its size isn't declared ,
+        /// so middleware can reject large uploads before the body is buffered
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::Request;
+        ///
+        /// let raw = b"POST /upload HTTP/1.1\r\nContent-Length: 5\r\nContent-Type: text/plain\r\n\r\nhello";
+        /// let (request, _) = Request::parse(raw).unwrap();
+        /// assert_eq!(request.content_length_or_chunked(), Some(5));
+        ///
+        /// let raw = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        /// let (request, _) = Request::parse(raw).unwrap();
+        /// assert_eq!(request.content_length_or_chunked(), None);
+        /// ```
+        pub fn content_length_or_chunked(&self) -> Option<u64> {
+            if self.find_header("Transfer-Encoding").is_some() {
+                return None;
             }
-            if filtered_route.starts_with("/") {
-                if let Some(method) = self
-                    .static_methods
-                    .get(&(request.method.clone(), request.route.clone()))
-                {
-                    let f = method.as_ref();
-                    f(&request, Response::new()).send(stream);
-                } else {
-                    let array: Vec<_> = filtered_route
-                        .split('/')
-                        .filter(|s| !s.is_empty())
-                        .collect();
-                    for (method_search, filtered_route, function) in self.dynamic_methods.iter() {
-                        if array.len() != filtered_route.len() || method != *method_search {
-                            continue;
-                        }
-                        let mut params_map = HashMap::new();
-                        for (index, pattern) in array.iter().enumerate() {
-                            match &filtered_route[index] {
-                                RouteSegment::Static(s) => {
-                                    if s != *pattern {
-                                        continue;
-                                    };
-                                }
-                                RouteSegment::Dynamic(s) => {
-                                    params_map.insert(s.to_string(), pattern.to_string());
-                                }
-                            }
-                        }
-                        request.params = Some(params_map);
-                        function(&request, response).send(stream);
-                        return;
+            return self
+                .find_header("Content-Length")
+                .and_then(|value| value.trim().parse().ok());
+        }
+
+        /// Parses the `Range` header into inclusive `(start, end)` byte ranges for a body of `total_len` bytes
+        ///
+        /// Returns `None` when there is no `Range` header or it isn't a valid `bytes=` range , the whole body should be sent \
+        /// Returns an empty list when none of the ranges can be satisfied , that is a 416 response \
+        /// Ends past the body are clamped to its last byte
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/video".to_string(), |request, response| {
+        ///        match request.parse_range(1000) {
+        ///            // "bytes=-100" gives [(900, 999)]
+        ///            Some(ranges) if ranges.is_empty() => response.status(416),
+        ///            Some(ranges) => response.status(206),
+        ///            None => response.status(200),
+        ///        }
+        ///    });
+        /// ```
+        ///
+        pub fn parse_range(&self, total_len: u64) -> Option<Vec<(u64, u64)>> {
+            let header = self.find_header("Range")?.trim();
+            let (unit, ranges) = header.split_once('=')?;
+            if !unit.trim().eq_ignore_ascii_case("bytes") {
+                return None;
+            }
+
+            let mut satisfiable = Vec::new();
+            for range in ranges.split(',') {
+                let (start, end) = range.trim().split_once('-')?;
+                let (start, end) = (start.trim(), end.trim());
+                if start.is_empty() {
+                    // "-N" is the last N bytes
+                    let suffix: u64 = end.parse().ok()?;
+                    if suffix > 0 && total_len > 0 {
+                        satisfiable.push((total_len.saturating_sub(suffix), total_len - 1));
                     }
+                    continue;
+                }
+                let start: u64 = start.parse().ok()?;
+                let end: u64 = match end {
+                    "" => u64::MAX,
+                    end => end.parse().ok()?,
+                };
+                if end < start {
+                    return None;
+                }
+                if start < total_len {
+                    satisfiable.push((start, end.min(total_len - 1)));
                 }
             }
+            return Some(satisfiable);
         }
 
-        fn add_new_route(&mut self, path: String, method: Method, function: Box<RouteFunction>) {
-            if path.contains(':') {
-                let mut vec = Vec::new();
-                let parts: Vec<_> = path
-                    .trim()
-                    .split('/')
-                    .filter(|s| !s.is_empty())
-                    .collect::<Vec<_>>();
-                parts.iter().for_each(|item| {
-                    if item.starts_with(':') {
-                        vec.push(RouteSegment::Dynamic(item[1..].to_string()))
-                    } else {
-                        vec.push(RouteSegment::Static(item.to_string()));
-                    }
-                });
-                self.dynamic_methods.push((method, vec, function));
-            } else {
-                self.static_methods.insert((method, path), function);
+        /// Returns true when the client's cached copy is still up to date , so a 304 can be sent instead of the body
+        ///
+        /// `If-None-Match` is compared with `etag` (weak comparison) , or when absent `If-Modified-Since` with `last_modified` \
+        /// Requests without these headers , or with `Cache-Control: no-cache` , are never fresh
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/report".to_string(), |request, response| {
+        ///        if request.fresh(Some("v42"), None) {
+        ///            return response.status(304);
+        ///        }
+        ///        response.status(200).etag("v42").html("<h1>Report</h1>".to_string())
+        ///    });
+        /// ```
+        ///
+        pub fn fresh(&self, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+            let no_cache = self.find_header("Cache-Control").is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+            });
+            if no_cache {
+                return false;
+            }
+
+            // W/"tag" , "tag" and tag all name the same tag under weak comparison
+            let opaque = |tag: &str| -> String {
+                let tag = tag.trim();
+                let tag = tag.strip_prefix("W/").unwrap_or(tag);
+                return tag.trim_matches('"').to_string();
+            };
+            if let Some(if_none_match) = self.find_header("If-None-Match") {
+                let etag = match etag {
+                    Some(etag) => opaque(etag),
+                    None => return false,
+                };
+                return if_none_match
+                    .split(',')
+                    .any(|tag| tag.trim() == "*" || opaque(tag) == etag);
+            }
+
+            if let (Some(since), Some(last_modified)) = (
+                self.find_header("If-Modified-Since")
+                    .and_then(|value| parse_http_date(value)),
+                last_modified,
+            ) {
+                // HTTP dates have a one second precision
+                let last_modified = last_modified
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs());
+                let since = since
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs());
+                return last_modified <= since;
             }
+            return false;
+        }
+
+        /// Returns true when the client's cached copy is out of date , the inverse of [Request::fresh]
+        pub fn stale(&self, etag: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+            return !self.fresh(etag, last_modified);
+        }
+
+        // true when the client asked to keep the connection open with `Connection: keep-alive`
+        fn wants_keep_alive(&self) -> bool {
+            return self.has_connection_token("keep-alive");
+        }
+
+        /// Returns true when the client can send another request on the connection after this one
+        ///
+        /// HTTP/1.1 keeps connections open unless `Connection: close` is sent ,
+        /// HTTP/1.0 closes them unless `Connection: keep-alive` is sent \
+        /// The server may still close the connection , when keep alive is disabled for example
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::Request;
+        ///
+        /// let (request, _) = Request::parse(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        /// assert!(request.is_keep_alive());
+        ///
+        /// let (request, _) = Request::parse(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        /// assert!(!request.is_keep_alive());
+        ///
+        /// let (request, _) = Request::parse(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        /// assert!(request.is_keep_alive());
+        /// ```
+        pub fn is_keep_alive(&self) -> bool {
+            if self.has_connection_token("close") {
+                return false;
+            }
+            return !self.is_http10() || self.wants_keep_alive();
+        }
+
+        fn is_http10(&self) -> bool {
+            return self.version == "HTTP/1.0";
+        }
+
+        fn has_connection_token(&self, token: &str) -> bool {
+            match self.find_header("Connection") {
+                Some(value) => value
+                    .split(',')
+                    .any(|listed| listed.trim().eq_ignore_ascii_case(token)),
+                None => false,
+            }
+        }
+
+        fn find_header(&self, name: &str) -> Option<&String> {
+            return header_value(&self.headers, name);
+        }
+    }
+    // split a `Cookie` header into its name=value pairs , values may contain '=' themselves
+    fn parse_cookies(header: &str) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        for pair in header.split(';') {
+            if let Some((name, value)) = pair.split_once('=') {
+                let name = name.trim();
+                if name.is_empty() {
+                    continue;
+                }
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                cookies.insert(name.to_string(), value.to_string());
+            }
+        }
+        return cookies;
+    }
+
+    /// The reasons [Request::parse] can fail
+    #[derive(Debug, PartialEq)]
+    pub enum ParseError {
+        /// The bytes end before the header or the body is complete
+        Incomplete,
+        /// The first line isn't `METHOD route version`
+        MalformedStartLine,
+        /// The method isn't a valid method token
+        UnknownMethod,
+        /// The `Content-Length` header isn't a valid number , or it is sent with `Transfer-Encoding`
+        InvalidContentLength,
+        /// The chunked body is malformed (see [chunked::decode])
+        InvalidChunkedBody,
+        /// The `Transfer-Encoding` doesn't end with `chunked` , so the end of the body can't be found
+        UnsupportedTransferEncoding,
+        /// The request line and headers are longer than 16384 bytes
+        HeadersTooLarge,
+        /// The request target is longer than 8192 bytes
+        UriTooLong,
+        /// The body is larger than the limit set for it (in bytes)
+        BodyTooLarge(usize),
+        /// The client started an HTTP/2 connection (`PRI * HTTP/2.0`) , only HTTP/1.x is supported
+        Http2Preface,
+    }
+
+    // limits on the parts of a request read before any handler runs
+    const MAX_HEADER_BYTES: usize = 16384;
+    const MAX_URI_LENGTH: usize = 8192;
+    // the body limit of a new application , `max_body_size` changes it
+    const DEFAULT_MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+    impl std::fmt::Display for ParseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ParseError::Incomplete => write!(f, "Connection Stopped before finishing"),
+                ParseError::MalformedStartLine => write!(f, "The Header is invalid (first line)"),
+                ParseError::UnknownMethod => write!(f, "Invalid Method"),
+                ParseError::InvalidContentLength => write!(f, "Invalid Content-Length"),
+                ParseError::InvalidChunkedBody => write!(f, "Invalid chunked body"),
+                ParseError::UnsupportedTransferEncoding => {
+                    write!(f, "Unsupported Transfer-Encoding")
+                }
+                ParseError::HeadersTooLarge => {
+                    write!(f, "Header fields exceed {} bytes", MAX_HEADER_BYTES)
+                }
+                ParseError::UriTooLong => write!(f, "Request URI exceeds {} bytes", MAX_URI_LENGTH),
+                ParseError::Http2Preface => write!(f, "HTTP/2 is not supported"),
+                ParseError::BodyTooLarge(limit) => {
+                    write!(f, "Request body exceeds {} bytes", limit)
+                }
+            }
+        }
+    }
+
+    impl std::error::Error for ParseError {}
+
+    impl ParseError {
+        // the response sent for a request that can't be parsed , None while more bytes may still come
+        fn error_response(&self) -> Option<ErrorResponse> {
+            let status = match self {
+                ParseError::Incomplete => return None,
+                ParseError::HeadersTooLarge => 431,
+                ParseError::UriTooLong => 414,
+                ParseError::Http2Preface => 505,
+                ParseError::BodyTooLarge(_) => 413,
+                ParseError::UnsupportedTransferEncoding => 501,
+                ParseError::MalformedStartLine
+                | ParseError::UnknownMethod
+                | ParseError::InvalidContentLength
+                | ParseError::InvalidChunkedBody => 400,
+            };
+            return Some(ErrorResponse::new(status, self.to_string()));
+        }
+    }
+
+    // why a request couldn't be read from a connection
+    #[derive(Debug)]
+    enum RequestError {
+        MalformedStartLine,
+        UnknownMethod,
+        // the connection was closed before the end of the request
+        IncompleteHeaders,
+        Io(std::io::Error),
+        // any other reason the request was refused (a limit , an invalid Content-Length ...)
+        Rejected(ParseError),
+    }
+
+    impl From<ParseError> for RequestError {
+        fn from(error: ParseError) -> RequestError {
+            match error {
+                ParseError::MalformedStartLine => RequestError::MalformedStartLine,
+                ParseError::UnknownMethod => RequestError::UnknownMethod,
+                ParseError::Incomplete => RequestError::IncompleteHeaders,
+                error => RequestError::Rejected(error),
+            }
+        }
+    }
+
+    impl RequestError {
+        // the response sent before closing the connection , None when the client can't be answered
+        fn error_response(&self) -> Option<ErrorResponse> {
+            match self {
+                RequestError::MalformedStartLine => ParseError::MalformedStartLine.error_response(),
+                RequestError::UnknownMethod => ParseError::UnknownMethod.error_response(),
+                RequestError::IncompleteHeaders => {
+                    Some(ErrorResponse::new(400, ParseError::Incomplete.to_string()))
+                }
+                RequestError::Io(error)
+                    if matches!(
+                        error.kind(),
+                        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    Some(ErrorResponse::new(408, "Request took too long to arrive"))
+                }
+                RequestError::Io(_) => None,
+                RequestError::Rejected(error) => error.error_response(),
+            }
+        }
+    }
+
+    // an error the server answers by itself (a limit was hit or the request is invalid) ,
+    // every one of them gets the same plain text body naming the problem
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct ErrorResponse {
+        pub(crate) status: i32,
+        pub(crate) message: String,
+    }
+
+    impl ErrorResponse {
+        pub(crate) fn new(status: i32, message: impl Into<String>) -> ErrorResponse {
+            return ErrorResponse {
+                status,
+                message: message.into(),
+            };
+        }
+
+        // the error as a response , a JSON body when `json_errors` is set (see `Response::error`)
+        pub(crate) fn into_response(self, json_errors: bool) -> Response {
+            let mut response = Response::new();
+            response.json_errors = json_errors;
+            return response.error(self.status, &self.message);
+        }
+
+        // answer with the error and tell the client the connection is closing
+        fn reject(self, json_errors: bool, stream: &mut Stream) {
+            let mut response = self.into_response(json_errors);
+            response.set_header("Connection", "close");
+            response.send(stream);
+        }
+    }
+
+    // the characters allowed in a method or header name (`tchar` in RFC 9110)
+    fn is_token_byte(byte: u8) -> bool {
+        return byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte);
+    }
+
+    // a header name must be a token and a value can't hold control characters (a CR or LF would start a new header) ,
+    // None when the name is invalid , otherwise the value without its control characters (tabs are kept)
+    fn sanitize_header(name: &str, value: &str) -> Option<String> {
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return None;
+        }
+        return Some(
+            value
+                .chars()
+                .filter(|c| *c == '\t' || !c.is_control())
+                .collect(),
+        );
+    }
+
+    // a cookie name is a token and its value is made of cookie-octets , optionally in double quotes (RFC 6265) ,
+    // an attribute value (Path , Domain) can't contain `;` or control characters
+    fn is_valid_cookie(name: &str, value: &str, attributes: &[&str]) -> bool {
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        let octet = |byte: u8| byte > 0x20 && byte < 0x7f && !b"\",;\\".contains(&byte);
+        return !name.is_empty()
+            && name.bytes().all(is_token_byte)
+            && value.bytes().all(octet)
+            && attributes.iter().all(|attribute| {
+                attribute
+                    .bytes()
+                    .all(|byte| byte >= 0x20 && byte != 0x7f && byte != b';')
+            });
+    }
+
+    // header names are case insensitive, so look them up ignoring case
+    fn header_value<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a String> {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// The `SameSite` attribute of a cookie
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SameSite {
+        Strict,
+        Lax,
+        None,
+    }
+
+    /// The attributes of a cookie set with [Response::cookie]
+    ///
+    /// Fields left unset aren't sent , except `path` which defaults to `/`
+    #[derive(Debug, Clone, Default)]
+    pub struct CookieOptions {
+        /// How long the cookie lives , it is a session cookie when `None`
+        pub max_age: Option<Duration>,
+        pub path: Option<String>,
+        pub domain: Option<String>,
+        /// Hide the cookie from scripts in the browser
+        pub http_only: bool,
+        /// Only send the cookie over https , `None` sets it for requests made over https
+        /// (see [Application::secure_cookies])
+        pub secure: Option<bool>,
+        pub same_site: Option<SameSite>,
+    }
+
+    // the pieces of a streamed body , each one is written as a chunk when it is produced
+    type Chunks = Box<dyn Iterator<Item = Vec<u8>> + Send>;
+
+    /// The Response struct is used to send a response to the client
+    /// It can be custom
+    ///
+    /// A handler that only sets a status sends it with an empty body and `Content-Length: 0`
+    pub struct Response {
+        status: i32,
+        content_type: Option<String>,
+        content_length: Option<usize>,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        // a body produced piece by piece , sent with chunked encoding instead of `body`
+        chunks: Option<Chunks>,
+        // send a body of known length with chunked encoding too
+        prefer_chunked: bool,
+        // set by `no_transform` , compression and chunking must leave the body untouched
+        no_transform: bool,
+        // copied from the application , picks the body format of `error`
+        json_errors: bool,
+        // set by `raw` , written instead of the status line , the headers and the body
+        raw: Option<Vec<u8>>,
+        // answering a HEAD request , the headers describe the body but it isn't sent
+        omit_body: bool,
+        // set by `bytes_with_etag` , a request whose cached copy matches the ETag gets a 304
+        conditional: bool,
+        // the request was made over https , cookies get the `Secure` flag unless their options say otherwise
+        secure_cookies: bool,
+    }
+
+    // lets middleware move the response out of a `&mut Response` with `std::mem::take`
+    impl Default for Response {
+        fn default() -> Self {
+            return Response::new();
+        }
+    }
+
+    impl Response {
+        fn new() -> Response {
+            Response {
+                status: 200,
+                content_length: None,
+                content_type: None,
+                headers: Vec::new(),
+                body: Vec::new(),
+                chunks: None,
+                prefer_chunked: false,
+                no_transform: false,
+                json_errors: false,
+                raw: None,
+                omit_body: false,
+                conditional: false,
+                secure_cookies: false,
+            }
+        }
+        /// A function to set the status code of the response
+        ///
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        response.status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn status(mut self, code: i32) -> Self {
+            self.status = code;
+            return self;
+        }
+        /// A function to set the body of the response to HTML
+        ///
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        response.status(200).html("<h1>Hello World</h1>".to_string())
+        ///    });
+        /// ```
+        ///
+        pub fn html(mut self, html: String) -> Self {
+            self.set_body("text/html", html.into_bytes());
+            return self;
+        }
+        /// A function to set the body of the response to plain text
+        ///
+        /// The content type is `text/plain; charset=utf-8` \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/health".to_string(), |request, response| {
+        ///        response.status(200).text("ok".to_string())
+        ///    });
+        /// ```
+        ///
+        pub fn text(mut self, body: String) -> Self {
+            self.set_body("text/plain; charset=utf-8", body.into_bytes());
+            return self;
+        }
+        /// A function to set a binary body of the response (an image , a PDF ...) with its content type
+        ///
+        /// The bytes are sent untouched \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/logo.png".to_string(), |request, response| {
+        ///        let png = std::fs::read("logo.png").unwrap();
+        ///        response.status(200).bytes(png, "image/png")
+        ///    });
+        /// ```
+        ///
+        pub fn bytes(mut self, data: Vec<u8>, content_type: &str) -> Self {
+            self.set_body(content_type, data);
+            return self;
+        }
+
+        /// A function to set a binary body like [Response::bytes] , with an `ETag` computed from its content
+        ///
+        /// When the `If-None-Match` header of the request matches the tag a 304 is sent without the body ,
+        /// the handler doesn't have to check [Request::fresh] itself \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/logo.png".to_string(), |request, response| {
+        ///        let png = std::fs::read("logo.png").unwrap();
+        ///        response.status(200).bytes_with_etag(png, "image/png")
+        ///    });
+        /// ```
+        ///
+        pub fn bytes_with_etag(self, data: Vec<u8>, content_type: &str) -> Self {
+            let tag = format!("{:x}-{:016x}", data.len(), content_hash(&data));
+            let mut response = self.bytes(data, content_type).etag(&tag);
+            response.conditional = true;
+            return response;
+        }
+
+        /// A function to send exactly `bytes` to the client , instead of the status line , the headers and the body
+        ///
+        /// This is an escape hatch , it bypasses every safety check of the response :
+        /// nothing is validated , no header is added and the bytes must frame the response themselves ,
+        /// or the connection is left in a broken state \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/custom".to_string(), |request, response| {
+        ///        response.raw(b"HTTP/1.1 299 Custom\r\nContent-Length: 2\r\n\r\nok".to_vec())
+        ///    });
+        /// ```
+        ///
+        pub fn raw(mut self, bytes: Vec<u8>) -> Self {
+            self.raw = Some(bytes);
+            return self;
+        }
+
+        /// A function to render a template as the HTML body of the response
+        ///
+        /// Every `{{key}}` is replaced by the value of `key` in `context` , HTML escaped so values can't inject markup \
+        /// Keys missing from `context` render as nothing , there is no other template logic \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        /// use std::collections::HashMap;
+        ///
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        let mut context = HashMap::new();
+        ///        context.insert("name".to_string(), "<b>omar</b>".to_string());
+        ///        // <h1>Hello &lt;b&gt;omar&lt;/b&gt;</h1>
+        ///        response.status(200).render("<h1>Hello {{name}}</h1>", &context)
+        ///    });
+        /// ```
+        ///
+        pub fn render(self, template: &str, context: &HashMap<String, String>) -> Self {
+            let mut html = String::with_capacity(template.len());
+            let mut rest = template;
+            while let Some(start) = rest.find("{{") {
+                let end = match rest[start + 2..].find("}}") {
+                    Some(end) => start + 2 + end,
+                    None => break,
+                };
+                html.push_str(&rest[..start]);
+                if let Some(value) = context.get(rest[start + 2..end].trim()) {
+                    html.push_str(&escape_html(value));
+                }
+                rest = &rest[end + 2..];
+            }
+            html.push_str(rest);
+            return self.html(html);
+        }
+        /// A function to answer with an error status and message , in the server's error format
+        ///
+        /// The body is `{"error": "<message>"}` as JSON when the application has [Application::json_errors] enabled ,
+        /// otherwise the message as `text/plain; charset=utf-8` \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/users".to_string(), |request, response| {
+        ///        if request.body().is_none() {
+        ///            return response.error(422, "invalid");
+        ///        }
+        ///        response.status(201)
+        ///    });
+        /// ```
+        ///
+        pub fn error(mut self, code: i32, message: &str) -> Self {
+            self.status = code;
+            let (content_type, body) = if self.json_errors {
+                let body = format!(r#"{{"error": "{}"}}"#, escape_json(message));
+                ("application/json", body)
+            } else {
+                ("text/plain; charset=utf-8", message.to_string())
+            };
+            self.set_body(content_type, body.into_bytes());
+            return self;
+        }
+        /// A function to set the body of the response to JSON
+        ///
+        /// It returns the response object , so it can be chained \
+        /// In debug builds the body is checked to be valid JSON and the handler panics when it isn't ,
+        /// release builds skip the check
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        response.status(200).json(r#"{"message": "Hello World"}"#)
+        ///    });
+        /// ```
+        ///
+        pub fn json(mut self, json: impl Into<String>) -> Self {
+            let json = json.into();
+            debug_assert!(is_valid_json(&json), "invalid JSON response body: {}", json);
+            self.set_body("application/json", json.into_bytes());
+            return self;
+        }
+        /// A function to disable caching of the response
+        ///
+        /// It sets `Cache-Control: no-store, no-cache, must-revalidate` , `Pragma: no-cache` and `Expires: 0` \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/account".to_string(), |request, response| {
+        ///        response.status(200).no_cache().json(r#"{"balance": 10}"#.to_string())
+        ///    });
+        /// ```
+        ///
+        pub fn no_cache(mut self) -> Self {
+            self.set_header("Cache-Control", "no-store, no-cache, must-revalidate");
+            self.set_header("Pragma", "no-cache");
+            self.set_header("Expires", "0");
+            return self;
+        }
+
+        /// A function to add a header name to the `Vary` header of the response
+        ///
+        /// Names already listed (compared case insensitively) are not added twice \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        response.vary("Accept").vary("Accept-Language").status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn vary(mut self, header_name: &str) -> Self {
+            self.append_to_header("Vary", header_name.trim());
+            return self;
+        }
+
+        /// A function to forbid proxies from transforming the response , and the server from compressing or chunking it
+        ///
+        /// It adds `no-transform` to the `Cache-Control` header , the body is sent as is with a `Content-Length` \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/image".to_string(), |request, response| {
+        ///        response.status(200).no_transform()
+        ///    });
+        /// ```
+        ///
+        pub fn no_transform(mut self) -> Self {
+            self.append_to_header("Cache-Control", "no-transform");
+            self.no_transform = true;
+            return self;
+        }
+
+        /// A function to remove a cookie from the client , by sending it expired with an empty value
+        ///
+        /// `path` must match the path the cookie was set with , nothing is sent when the name or the path is invalid (see [Response::cookie]) \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.post("/logout".to_string(), |request, response| {
+        ///        response.clear_cookie("session", "/").status(204)
+        ///    });
+        /// ```
+        ///
+        pub fn clear_cookie(mut self, name: &str, path: &str) -> Self {
+            if !is_valid_cookie(name, "", &[path]) {
+                return self;
+            }
+            self.push_header(
+                "Set-Cookie",
+                &format!(
+                    "{}=; Path={}; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+                    name, path
+                ),
+            );
+            return self;
+        }
+
+        /// A function to set a cookie on the client
+        ///
+        /// Every call sends its own `Set-Cookie` header , so several cookies can be set on one response \
+        /// The cookie is not set when the name isn't a token , the value contains characters not allowed in a cookie
+        /// (spaces , `;` , `,` , `"` , `\\` , control characters) or the path or domain contains `;` or control characters ,
+        /// so user input can't add attributes or headers (encode such values first , with base64 or percent encoding) \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        /// use express::{CookieOptions, SameSite};
+        /// use std::time::Duration;
+        ///
+        ///    app.post("/login".to_string(), |request, response| {
+        ///        let options = CookieOptions {
+        ///            max_age: Some(Duration::from_secs(3600)),
+        ///            http_only: true,
+        ///            same_site: Some(SameSite::Lax),
+        ///            ..Default::default()
+        ///        };
+        ///        response
+        ///            .cookie("session", "abc", options)
+        ///            .cookie("theme", "dark", CookieOptions::default())
+        ///            .status(204)
+        ///    });
+        /// ```
+        ///
+        pub fn cookie(mut self, name: &str, value: &str, options: CookieOptions) -> Self {
+            let path = options.path.as_deref().unwrap_or("/");
+            let domain = options.domain.as_deref().unwrap_or("");
+            if !is_valid_cookie(name, value, &[path, domain]) {
+                return self;
+            }
+            let mut cookie = format!("{}={}; Path={}", name, value, path);
+            if let Some(domain) = &options.domain {
+                cookie.push_str(&format!("; Domain={}", domain));
+            }
+            if let Some(max_age) = options.max_age {
+                let expires = SystemTime::now() + max_age;
+                cookie.push_str(&format!(
+                    "; Max-Age={}; Expires={}",
+                    max_age.as_secs(),
+                    http_date(expires)
+                ));
+            }
+            if options.http_only {
+                cookie.push_str("; HttpOnly");
+            }
+            if options.secure.unwrap_or(self.secure_cookies) {
+                cookie.push_str("; Secure");
+            }
+            if let Some(same_site) = options.same_site {
+                let same_site = match same_site {
+                    SameSite::Strict => "Strict",
+                    SameSite::Lax => "Lax",
+                    SameSite::None => "None",
+                };
+                cookie.push_str(&format!("; SameSite={}", same_site));
+            }
+            self.push_header("Set-Cookie", &cookie);
+            return self;
+        }
+
+        /// A function to set a header of the response
+        ///
+        /// Setting a header again overwrites it (names are case insensitive) \
+        /// `Content-Type` replaces the type set by the body functions , `Content-Length` is always computed from the body and is ignored \
+        /// A name that isn't a valid token is ignored and control characters (CR , LF ...) are removed from the value ,
+        /// so user input can't add headers to the response \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/old".to_string(), |request, response| {
+        ///        response
+        ///            .status(301)
+        ///            .header("Location", "/new")
+        ///            .header("X-Powered-By", "express_rs")
+        ///    });
+        /// ```
+        ///
+        pub fn header(mut self, name: &str, value: &str) -> Self {
+            if name.eq_ignore_ascii_case("Content-Type") {
+                self.content_type = sanitize_header(name, value);
+            } else if !name.eq_ignore_ascii_case("Content-Length") {
+                self.set_header(name, value);
+            }
+            return self;
+        }
+
+        /// A function to set a header only when the response doesn't have it yet (names are case insensitive)
+        ///
+        /// Useful for defaults that must not override what a handler set \
+        /// `Content-Type` and `Content-Length` are handled like with [Response::header] \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/hello".to_string(), |request, response| {
+        ///        response
+        ///            .no_cache()
+        ///            // skipped , no_cache already set Cache-Control
+        ///            .insert_header_if_absent("Cache-Control", "max-age=60")
+        ///            .status(200)
+        ///    });
+        /// ```
+        ///
+        pub fn insert_header_if_absent(self, name: &str, value: &str) -> Self {
+            if self.has_header(name) {
+                return self;
+            }
+            return self.header(name, value);
+        }
+
+        /// A function to redirect the client back to the page it came from (the `Referer` header) with a 302
+        ///
+        /// `fallback` is used when there is no `Referer` , or when it points to another host or contains control characters ,
+        /// so the redirect can't be used to send users to another site \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::{Request, Response};
+        ///
+        /// let raw = b"POST /comment HTTP/1.1\r\nHost: example.com\r\nReferer: https://example.com/post/7\r\n\r\n";
+        /// let (request, _) = Request::parse(raw).unwrap();
+        /// let bytes = Response::default().redirect_back(&request, "/").to_bytes();
+        /// assert!(String::from_utf8(bytes).unwrap().contains("Location: https://example.com/post/7\r\n"));
+        ///
+        /// let (request, _) = Request::parse(b"POST /comment HTTP/1.1\r\nHost: example.com\r\n\r\n").unwrap();
+        /// let bytes = Response::default().redirect_back(&request, "/").to_bytes();
+        /// assert!(String::from_utf8(bytes).unwrap().contains("Location: /\r\n"));
+        /// ```
+        pub fn redirect_back(self, request: &Request, fallback: &str) -> Self {
+            let location = match request.find_header("Referer") {
+                Some(referer) if is_safe_referer(referer, request.host()) => referer.as_str(),
+                _ => fallback,
+            };
+            return self.status(302).header("Location", location);
+        }
+
+        /// A function to add many headers at once , when forwarding the headers of another response for example
+        ///
+        /// Headers are appended , a name given more than once (`Set-Cookie`) is sent once per value \
+        /// `Content-Type` replaces the type set by the body functions and `Content-Length` is ignored , like with [Response::header] \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::Response;
+        ///
+        /// let headers = vec![
+        ///     ("X-Request-Id".to_string(), "42".to_string()),
+        ///     ("Cache-Control".to_string(), "no-store".to_string()),
+        ///     ("X-Frame-Options".to_string(), "DENY".to_string()),
+        ///     ("Set-Cookie".to_string(), "a=1".to_string()),
+        ///     ("Set-Cookie".to_string(), "b=2".to_string()),
+        /// ];
+        /// let bytes = Response::default().status(200).with_headers(headers).to_bytes();
+        /// let text = String::from_utf8(bytes).unwrap();
+        /// for line in ["X-Request-Id: 42", "Cache-Control: no-store", "X-Frame-Options: DENY", "Set-Cookie: a=1", "Set-Cookie: b=2"] {
+        ///     assert!(text.contains(line));
+        /// }
+        /// ```
+        pub fn with_headers(mut self, headers: Vec<(String, String)>) -> Self {
+            for (name, value) in headers {
+                if name.eq_ignore_ascii_case("Content-Type") {
+                    self.content_type = sanitize_header(&name, &value);
+                } else if !name.eq_ignore_ascii_case("Content-Length") {
+                    self.push_header(&name, &value);
+                }
+            }
+            return self;
+        }
+
+        /// Returns the bytes sent to the client for this response , the status line , the headers and the body
+        ///
+        /// Streamed bodies are read to the end and chunk encoded \
+        /// Useful to test handlers without a socket , the headers the server adds when sending (`Connection` , `Keep-Alive` ...) aren't included
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::Response;
+        ///
+        /// let bytes = Response::default().status(200).text("hi".to_string()).to_bytes();
+        /// assert!(bytes.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        /// assert!(bytes.ends_with(b"\r\n\r\nhi"));
+        /// ```
+        pub fn to_bytes(mut self) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            // writing to a Vec can't fail
+            let _ = self.write_to(&mut bytes);
+            return bytes;
+        }
+
+        /// A function to forward an upstream HTTP response (status , headers and body) , for proxying
+        ///
+        /// `upstream` is read from its status line to the end of the body , the body is copied with `io::copy` \
+        /// Hop-by-hop headers (`Connection` and the headers it lists , `Keep-Alive` , `Proxy-Connection` , `TE` , `Trailer` , `Upgrade`)
+        /// are not forwarded , chunked upstream bodies are decoded (see [chunked::decode]) and other transfer codings
+        /// are rejected with an `InvalidData` error \
+        /// 1xx , 204 and 304 responses have no body , any other response without a `Content-Length` or chunked encoding
+        /// is read until the upstream closes the connection , so a keep-alive upstream (or the answer to a HEAD request)
+        /// must send one of them or close the connection \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        /// use std::io::Write;
+        /// use std::net::TcpStream;
+        ///
+        ///    app.get("/proxy".to_string(), |request, response| {
+        ///        let mut upstream = TcpStream::connect("127.0.0.1:9000").unwrap();
+        ///        upstream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        ///        response.pipe(upstream).unwrap()
+        ///    });
+        /// ```
+        ///
+        pub fn pipe<R: Read>(mut self, upstream: R) -> std::io::Result<Self> {
+            let invalid =
+                |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+            let mut upstream = BufReader::new(upstream);
+
+            let mut status_line = String::new();
+            upstream.read_line(&mut status_line)?;
+            self.status = match status_line.split_ascii_whitespace().nth(1) {
+                Some(status) => status
+                    .parse()
+                    .map_err(|_| invalid("invalid upstream status"))?,
+                None => return Err(invalid("invalid upstream status line")),
+            };
+
+            let mut content_length = None;
+            let mut chunked = false;
+            let mut headers: Vec<(String, String)> = Vec::new();
+            // the headers named in `Connection` only concern the upstream connection
+            let mut connection_options: Vec<String> = Vec::new();
+            loop {
+                let mut line = String::new();
+                if upstream.read_line(&mut line)? == 0 {
+                    return Err(invalid("upstream headers ended early"));
+                }
+                let line = line.trim();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = match line.split_once(':') {
+                    Some((name, value)) => (name.trim(), value.trim()),
+                    None => continue,
+                };
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    let length = value
+                        .parse::<u64>()
+                        .map_err(|_| invalid("invalid upstream Content-Length"))?;
+                    content_length = Some(length);
+                } else if name.eq_ignore_ascii_case("Content-Type") {
+                    self.content_type = sanitize_header(name, value);
+                } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                    if !value.eq_ignore_ascii_case("chunked") {
+                        return Err(invalid("unsupported upstream Transfer-Encoding"));
+                    }
+                    chunked = true;
+                } else if name.eq_ignore_ascii_case("Connection") {
+                    connection_options
+                        .extend(value.split(',').map(|option| option.trim().to_string()));
+                } else if !HOP_BY_HOP_HEADERS
+                    .iter()
+                    .any(|hop| name.eq_ignore_ascii_case(hop))
+                {
+                    headers.push((name.to_string(), value.to_string()));
+                }
+            }
+            for (name, value) in headers {
+                if !connection_options
+                    .iter()
+                    .any(|option| name.eq_ignore_ascii_case(option))
+                {
+                    self.push_header(&name, &value);
+                }
+            }
+
+            // without a Content-Length the upstream body ends when the upstream connection closes ,
+            // 1xx , 204 and 304 responses have no body whatever the framing headers say
+            let mut body = Vec::new();
+            if status_allows_body(self.status) {
+                if chunked {
+                    body = chunked::decode(&mut upstream)
+                        .map_err(|error| invalid(&error.to_string()))?;
+                } else {
+                    match content_length {
+                        Some(length) => std::io::copy(&mut upstream.take(length), &mut body)?,
+                        None => std::io::copy(&mut upstream, &mut body)?,
+                    };
+                }
+            }
+            let content_type = match self.content_type.take() {
+                Some(content_type) => content_type,
+                None if !body.is_empty() => "application/octet-stream".to_string(),
+                None => String::new(),
+            };
+            self.set_body(&content_type, body);
+            if content_type.is_empty() {
+                self.content_type = None;
+            }
+            return Ok(self);
+        }
+
+        /// A function to set the `Last-Modified` header , formatted as an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`)
+        ///
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        /// use std::time::SystemTime;
+        ///
+        ///    app.get("/report".to_string(), |request, response| {
+        ///        response.status(200).last_modified(SystemTime::now())
+        ///    });
+        /// ```
+        ///
+        pub fn last_modified(mut self, time: SystemTime) -> Self {
+            self.set_header("Last-Modified", &http_date(time));
+            return self;
+        }
+
+        /// A function to set the `ETag` header
+        ///
+        /// The tag is wrapped in quotes when it isn't already , a `W/` prefix marks a weak tag and is kept outside the quotes \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/report".to_string(), |request, response| {
+        ///        // ETag: W/"v42"
+        ///        response.status(200).etag("W/v42")
+        ///    });
+        /// ```
+        ///
+        pub fn etag(mut self, tag: &str) -> Self {
+            let (weak, tag) = match tag.strip_prefix("W/") {
+                Some(tag) => ("W/", tag),
+                None => ("", tag),
+            };
+            let value = if tag.len() >= 2 && tag.starts_with('"') && tag.ends_with('"') {
+                format!("{}{}", weak, tag)
+            } else {
+                format!("{}\"{}\"", weak, tag)
+            };
+            self.set_header("ETag", &value);
+            return self;
+        }
+
+        /// A function to send the body with chunked encoding even when its length is known
+        ///
+        /// Streamed bodies are always chunked , other bodies get a `Content-Length` unless this is enabled \
+        /// HTTP/1.0 clients and [Response::no_transform] responses still get a `Content-Length` \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/chunked".to_string(), |request, response| {
+        ///        response.status(200).html("<h1>Hello</h1>".to_string()).prefer_chunked(true)
+        ///    });
+        /// ```
+        ///
+        pub fn prefer_chunked(mut self, enabled: bool) -> Self {
+            self.prefer_chunked = enabled;
+            return self;
+        }
+
+        /// A function to stream a JSON array built from already serialized items
+        ///
+        /// The items are written one by one with chunked encoding as the iterator produces them ,
+        /// so the whole array is never held in memory \
+        /// It returns the response object , so it can be chained
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// # let mut app = express::Application::new();
+        ///    app.get("/numbers".to_string(), |request, response| {
+        ///        // [{"n":0},{"n":1},...]
+        ///        let items = (0..10_000).map(|n| format!(r#"{{"n":{}}}"#, n));
+        ///        response.status(200).stream_json_array(items)
+        ///    });
+        /// ```
+        ///
+        pub fn stream_json_array<I>(mut self, items: I) -> Self
+        where
+            I: Iterator<Item = String> + Send + 'static,
+        {
+            let elements = items.enumerate().map(|(index, item)| {
+                let mut element = Vec::with_capacity(item.len() + 1);
+                if index > 0 {
+                    element.push(b',');
+                }
+                element.extend_from_slice(item.as_bytes());
+                element
+            });
+            let array = std::iter::once(b"[".to_vec())
+                .chain(elements)
+                .chain(std::iter::once(b"]".to_vec()));
+            self.set_body("application/json", Vec::new());
+            self.content_length = None;
+            self.chunks = Some(Box::new(array));
+            return self;
+        }
+
+        // true when the header is set , including the content headers set by the body functions
+        fn has_header(&self, name: &str) -> bool {
+            if name.eq_ignore_ascii_case("Content-Type") && self.content_type.is_some() {
+                return true;
+            }
+            if name.eq_ignore_ascii_case("Content-Length") && self.content_length.is_some() {
+                return true;
+            }
+            return self
+                .headers
+                .iter()
+                .any(|(key, _)| key.eq_ignore_ascii_case(name));
+        }
+
+        fn find_header(&self, name: &str) -> Option<&String> {
+            return self
+                .headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value);
+        }
+
+        // add a token to a comma separated header , tokens already listed (ignoring case) are not added twice
+        fn append_to_header(&mut self, name: &str, token: &str) {
+            let current = self
+                .headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone());
+            let value = match current {
+                Some(current) => {
+                    let listed = current
+                        .split(',')
+                        .any(|listed| listed.trim().eq_ignore_ascii_case(token));
+                    if listed {
+                        return;
+                    }
+                    format!("{}, {}", current, token)
+                }
+                None => token.to_string(),
+            };
+            self.set_header(name, &value);
+        }
+        // set a header , replacing any previous value of the same (case insensitive) name
+        fn set_header(&mut self, name: &str, value: &str) {
+            self.headers
+                .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+            self.push_header(name, value);
+        }
+        // add a header , dropped when the name is invalid and stripped of control characters
+        fn push_header(&mut self, name: &str, value: &str) {
+            if let Some(value) = sanitize_header(name, value) {
+                self.headers.push((name.to_string(), value));
+            }
+        }
+        // replace the body (a streamed one too) and its content headers
+        fn set_body(&mut self, content_type: &str, body: Vec<u8>) {
+            self.content_type = Some(content_type.to_string());
+            self.content_length = Some(body.len());
+            self.body = body;
+            self.chunks = None;
+        }
+
+        // gzip the body when the client accepts it and it is large enough to be worth it ,
+        // caches must then keep one copy per Accept-Encoding
+        #[cfg(feature = "gzip")]
+        fn compress(&mut self, request: &Request, min_size: usize) {
+            let content_type = match &self.content_type {
+                Some(content_type) if is_compressible(content_type) => content_type.clone(),
+                _ => return,
+            };
+            // a 206 body is a range of the uncompressed representation
+            if self.no_transform
+                || self.raw.is_some()
+                || self.chunks.is_some()
+                || self.status == 206
+                || !status_allows_body(self.status)
+                || self.body.len() < min_size
+                || self.has_header("Content-Encoding")
+            {
+                return;
+            }
+            self.append_to_header("Vary", "Accept-Encoding");
+            if !request
+                .find_header("Accept-Encoding")
+                .is_some_and(|value| accepts_gzip(value))
+            {
+                return;
+            }
+            let compressed = match gzip(&self.body) {
+                Some(compressed) if compressed.len() < self.body.len() => compressed,
+                _ => return,
+            };
+            self.set_body(&content_type, compressed);
+            self.set_header("Content-Encoding", "gzip");
+            // the compressed bytes differ from the ones a strong ETag was computed from
+            if let Some(etag) = self.find_header("ETag").cloned()
+                && !etag.starts_with("W/")
+            {
+                self.set_header("ETag", &format!("W/{}", etag));
+            }
+        }
+
+        // read a streamed body to the end , for the clients that can't receive chunks
+        fn collect_chunks(&mut self) {
+            if let Some(chunks) = self.chunks.take() {
+                let content_type = self.content_type.take().unwrap_or_default();
+                self.set_body(&content_type, chunks.flatten().collect());
+            }
+        }
+
+        fn send<W: Write>(&mut self, stream: &mut W) {
+            // the status line , every header and the body are separate small writes ,
+            // buffer them so they reach the socket together
+            // a client that went away can't be answered , the next read on the connection ends it
+            let mut writer = BufWriter::new(stream);
+            if self.write_to(&mut writer).is_ok() {
+                let _ = writer.flush();
+            }
+        }
+
+        // write the status line , headers and body as they are sent on the wire
+        fn write_to<W: Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+            if let Some(raw) = &self.raw {
+                return writer.write_all(raw);
+            }
+            // streamed bodies are chunked , bodies of known length get a Content-Length unless
+            // `prefer_chunked` asks otherwise , `no_transform` always sends a Content-Length
+            if self.no_transform {
+                self.collect_chunks();
+            } else if self.prefer_chunked && self.chunks.is_none() && self.content_length.is_some()
+            {
+                let body = std::mem::take(&mut self.body);
+                self.chunks = Some(Box::new(std::iter::once(body)));
+            }
+            write!(
+                writer,
+                "HTTP/1.1 {} {}\r\n",
+                self.status,
+                reason_phrase(self.status)
+            )?;
+            let allows_body = status_allows_body(self.status);
+            let chunks = match self.chunks.take() {
+                Some(chunks) if allows_body => Some(chunks),
+                _ => None,
+            };
+            if chunks.is_some() {
+                writer.write_all(b"Transfer-Encoding: chunked\r\n")?;
+            } else if allows_body {
+                // without a body say so , or the client waits for one until the connection closes
+                write!(
+                    writer,
+                    "Content-Length: {}\r\n",
+                    self.content_length.unwrap_or(0)
+                )?;
+            }
+            // the content type is kept even without a body , set with `header("Content-Type", ...)`
+            if allows_body && let Some(content_type) = &self.content_type {
+                write!(writer, "Content-Type: {}\r\n", content_type)?;
+            }
+            for (name, value) in &self.headers {
+                write!(writer, "{}: {}\r\n", name, value)?;
+            }
+            writer.write_all(b"\r\n")?;
+            if self.omit_body {
+                return Ok(());
+            }
+            if let Some(chunks) = chunks {
+                for chunk in chunks {
+                    chunked::write_chunk(writer, &chunk)?;
+                }
+                writer.write_all(chunked::LAST_CHUNK)?;
+            } else if allows_body && self.content_length.is_some() {
+                writer.write_all(&self.body)?;
+            }
+            return Ok(());
+        }
+    }
+
+    /// The chunked transfer coding , the framing of bodies sent without a `Content-Length`
+    ///
+    /// A chunked body is a series of `<size in hex>\r\n<data>\r\n` chunks ended by a chunk of size 0 ,
+    /// optionally followed by trailer fields and an empty line
+    ///
+    /// # Example:
+    /// ```rust
+    /// use express_rs::express::chunked;
+    ///
+    /// let encoded = chunked::encode(b"hello world");
+    /// assert_eq!(encoded, b"b\r\nhello world\r\n0\r\n\r\n");
+    /// assert_eq!(chunked::decode(&encoded[..]).unwrap(), b"hello world");
+    /// ```
+    pub mod chunked {
+        use std::io::{BufRead, Read, Write};
+
+        // the last chunk and the empty line ending a body without trailers
+        pub(crate) const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+        // the longest chunk size line (with its extensions) or trailer line accepted
+        const MAX_LINE_BYTES: u64 = 4096;
+        // the most trailer fields accepted after the last chunk
+        const MAX_TRAILERS: usize = 100;
+
+        /// Why a chunked body couldn't be decoded
+        #[derive(Debug)]
+        pub enum ChunkError {
+            /// A chunk size isn't a hexadecimal number , is too large or its line is too long
+            InvalidSize,
+            /// A line or the data of a chunk isn't followed by `\r\n`
+            MissingCrlf,
+            /// A trailer field isn't a `name: value` line , or there are too many of them
+            InvalidTrailer,
+            /// The input ended before the last chunk and the end of the trailers
+            UnexpectedEof,
+            /// The decoded body is larger than the limit (in bytes)
+            TooLarge(usize),
+            /// Reading the input failed
+            Io(std::io::Error),
+        }
+
+        impl std::fmt::Display for ChunkError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    ChunkError::InvalidSize => write!(f, "Invalid chunk size"),
+                    ChunkError::MissingCrlf => write!(f, "Chunk is not followed by CRLF"),
+                    ChunkError::InvalidTrailer => write!(f, "Invalid chunked trailer"),
+                    ChunkError::UnexpectedEof => write!(f, "Chunked body ended early"),
+                    ChunkError::TooLarge(limit) => {
+                        write!(f, "Chunked body exceeds {} bytes", limit)
+                    }
+                    ChunkError::Io(error) => write!(f, "{}", error),
+                }
+            }
+        }
+
+        impl std::error::Error for ChunkError {}
+
+        impl From<std::io::Error> for ChunkError {
+            fn from(error: std::io::Error) -> ChunkError {
+                return ChunkError::Io(error);
+            }
+        }
+
+        /// Decode a chunked body , reading `reader` up to the end of the trailers
+        ///
+        /// Chunk extensions (`5;name=value`) and trailer fields are checked then dropped ,
+        /// nothing after the body is read so a `&mut &[u8]` tells where the next message starts \
+        /// Malformed input is an error , it never panics nor loops on it
+        ///
+        /// # Example:
+        /// ```rust
+        /// use express_rs::express::chunked::{self, ChunkError};
+        ///
+        /// let mut input: &[u8] = b"4;note=first\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\nnext";
+        /// assert_eq!(chunked::decode(&mut input).unwrap(), b"Wikipedia");
+        /// assert_eq!(input, b"next");
+        ///
+        /// let decode = |input: &[u8]| chunked::decode(input).unwrap_err();
+        /// assert!(matches!(decode(b"zz\r\nWiki\r\n0\r\n\r\n"), ChunkError::InvalidSize));
+        /// assert!(matches!(decode(b"\r\nWiki\r\n0\r\n\r\n"), ChunkError::InvalidSize));
+        /// assert!(matches!(decode(b"ffffffffffffffffff\r\n"), ChunkError::InvalidSize));
+        /// assert!(matches!(decode(b"4\r\nWikipedia\r\n0\r\n\r\n"), ChunkError::MissingCrlf));
+        /// assert!(matches!(decode(b"4\nWiki\n0\n\n"), ChunkError::MissingCrlf));
+        /// assert!(matches!(decode(b"0\r\nnot a field\r\n\r\n"), ChunkError::InvalidTrailer));
+        /// assert!(matches!(decode(b"4\r\nWi"), ChunkError::UnexpectedEof));
+        /// assert!(matches!(decode(b"4\r\nWiki\r\n"), ChunkError::UnexpectedEof));
+        /// assert!(matches!(decode(b"0\r\n"), ChunkError::UnexpectedEof));
+        /// ```
+        pub fn decode<R: BufRead>(reader: R) -> Result<Vec<u8>, ChunkError> {
+            return decode_limited(reader, None);
+        }
+
+        // `decode` , failing with `TooLarge` as soon as a chunk would make the body larger than `limit`
+        pub(crate) fn decode_limited<R: BufRead>(
+            mut reader: R,
+            limit: Option<usize>,
+        ) -> Result<Vec<u8>, ChunkError> {
+            let mut body = Vec::new();
+            loop {
+                let line = read_line(&mut reader, ChunkError::InvalidSize)?;
+                // extensions follow the size after a `;`
+                let size = line.split(|byte| *byte == b';').next().unwrap_or(&[]);
+                let size = size.trim_ascii();
+                if size.is_empty() || size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(ChunkError::InvalidSize);
+                }
+                // only hex digits , so this is valid UTF-8
+                let size = u64::from_str_radix(std::str::from_utf8(size).unwrap(), 16)
+                    .map_err(|_| ChunkError::InvalidSize)?;
+                if size == 0 {
+                    break;
+                }
+                if let Some(limit) = limit
+                    && body.len() as u64 + size > limit as u64
+                {
+                    return Err(ChunkError::TooLarge(limit));
+                }
+                // the data is read as it arrives , a huge size alone allocates nothing
+                let read = reader.by_ref().take(size).read_to_end(&mut body)?;
+                if (read as u64) < size {
+                    return Err(ChunkError::UnexpectedEof);
+                }
+                let mut crlf = [0u8; 2];
+                match reader.read_exact(&mut crlf) {
+                    Ok(()) if &crlf == b"\r\n" => {}
+                    Ok(()) => return Err(ChunkError::MissingCrlf),
+                    Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                        return Err(ChunkError::UnexpectedEof);
+                    }
+                    Err(error) => return Err(ChunkError::Io(error)),
+                }
+            }
+            for _ in 0..=MAX_TRAILERS {
+                let line = read_line(&mut reader, ChunkError::InvalidTrailer)?;
+                if line.is_empty() {
+                    return Ok(body);
+                }
+                match line.iter().position(|byte| *byte == b':') {
+                    Some(colon) if colon > 0 => {}
+                    _ => return Err(ChunkError::InvalidTrailer),
+                }
+            }
+            return Err(ChunkError::InvalidTrailer);
+        }
+
+        /// Encode `data` as a chunked body , one chunk followed by the last chunk
+        pub fn encode(data: &[u8]) -> Vec<u8> {
+            let mut encoded = Vec::with_capacity(data.len() + 16);
+            // writing to a Vec can't fail
+            let _ = write_chunk(&mut encoded, data);
+            encoded.extend_from_slice(LAST_CHUNK);
+            return encoded;
+        }
+
+        // write `data` as one chunk , nothing for empty data which would read as the last chunk
+        pub(crate) fn write_chunk<W: Write>(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+            if data.is_empty() {
+                return Ok(());
+            }
+            write!(writer, "{:x}\r\n", data.len())?;
+            writer.write_all(data)?;
+            writer.write_all(b"\r\n")?;
+            return Ok(());
+        }
+
+        // read a line ending with CRLF and return it without the CRLF ,
+        // `too_long` is the error for a line longer than the limit
+        fn read_line<R: BufRead>(
+            reader: &mut R,
+            too_long: ChunkError,
+        ) -> Result<Vec<u8>, ChunkError> {
+            let mut line = Vec::new();
+            reader
+                .by_ref()
+                .take(MAX_LINE_BYTES)
+                .read_until(b'\n', &mut line)?;
+            if line.last() != Some(&b'\n') {
+                if line.len() as u64 >= MAX_LINE_BYTES {
+                    return Err(too_long);
+                }
+                return Err(ChunkError::UnexpectedEof);
+            }
+            line.pop();
+            if line.pop() != Some(b'\r') {
+                return Err(ChunkError::MissingCrlf);
+            }
+            return Ok(line);
+        }
+    }
+
+    // true for the media types that shrink when compressed (text , JSON , JavaScript , XML)
+    #[cfg(feature = "gzip")]
+    fn is_compressible(content_type: &str) -> bool {
+        let essence = essence(content_type);
+        return essence.starts_with("text/")
+            || essence.ends_with("+json")
+            || essence.ends_with("+xml")
+            || [
+                "application/json",
+                "application/javascript",
+                "application/xml",
+            ]
+            .contains(&essence.as_str());
+    }
+
+    // true when an Accept-Encoding value accepts gzip , by name or with `*` (`q=0` refuses it)
+    #[cfg(feature = "gzip")]
+    fn accepts_gzip(accept_encoding: &str) -> bool {
+        let mut any = false;
+        for coding in accept_encoding.split(',') {
+            let mut parts = coding.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let quality = parts
+                .find_map(|param| {
+                    let (key, value) = param.split_once('=')?;
+                    key.trim().eq_ignore_ascii_case("q").then(|| value.trim())
+                })
+                .map_or(1.0, |quality| quality.parse::<f32>().unwrap_or(0.0));
+            if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+                return quality > 0.0;
+            }
+            if name == "*" {
+                any = quality > 0.0;
+            }
+        }
+        return any;
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Option<Vec<u8>> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).ok()?;
+        return encoder.finish().ok();
+    }
+
+    // true when a referer can be redirected to : a path on this site , or an absolute URL on `host` ,
+    // without control characters that could split the header
+    fn is_safe_referer(referer: &str, host: Option<&str>) -> bool {
+        if referer.chars().any(|character| character.is_control()) {
+            return false;
+        }
+        // "//evil.com" and "/\evil.com" are read as other hosts by browsers
+        if referer.starts_with('/') {
+            return !referer.starts_with("//") && !referer.starts_with("/\\");
+        }
+        let rest = referer
+            .strip_prefix("https://")
+            .or_else(|| referer.strip_prefix("http://"));
+        let (Some(rest), Some(host)) = (rest, host) else {
+            return false;
+        };
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        return authority.eq_ignore_ascii_case(host);
+    }
+
+    // 64 bit FNV-1a hash of a body , stable across builds so ETags survive restarts
+    fn content_hash(bytes: &[u8]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        return hash;
+    }
+
+    // escape a string to be put between the quotes of a JSON string
+    fn escape_json(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for character in text.chars() {
+            match character {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                other if (other as u32) < 0x20 => {
+                    escaped.push_str(&format!("\\u{:04x}", other as u32))
+                }
+                other => escaped.push(other),
+            }
+        }
+        return escaped;
+    }
+
+    // escape the characters that are special in HTML text and attribute values
+    fn escape_html(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for character in text.chars() {
+            match character {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                other => escaped.push(other),
+            }
+        }
+        return escaped;
+    }
+
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    // format a time as an IMF-fixdate (RFC 9110) , times before 1970 are clamped to the epoch
+    fn http_date(time: SystemTime) -> String {
+        const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let days = seconds / 86400;
+        let seconds_of_day = seconds % 86400;
+
+        // days since the epoch to a civil date (Howard Hinnant's algorithm) , counted from 0000-03-01
+        let shifted = days + 719468;
+        let era = shifted / 146097;
+        let day_of_era = shifted % 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        return format!(
+            "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+            DAYS[(days % 7) as usize],
+            day,
+            MONTHS[(month - 1) as usize],
+            year,
+            seconds_of_day / 3600,
+            seconds_of_day % 3600 / 60,
+            seconds_of_day % 60
+        );
+    }
+
+    // parse an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) , the obsolete date formats are not supported
+    fn parse_http_date(text: &str) -> Option<SystemTime> {
+        let (_, date) = text.trim().split_once(", ")?;
+        let parts: Vec<&str> = date.split(' ').collect();
+        if parts.len() != 5 || parts[4] != "GMT" {
+            return None;
+        }
+        let day: u64 = parts[0].parse().ok()?;
+        let month = MONTHS.iter().position(|month| *month == parts[1])? as u64 + 1;
+        let year: u64 = parts[2].parse().ok()?;
+        let time: Vec<u64> = parts[3]
+            .split(':')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        if year < 1970 || !(1..=31).contains(&day) || time.len() != 3 {
+            return None;
+        }
+
+        // a civil date to days since the epoch (the inverse of the algorithm in `http_date`)
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let month_index = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+
+        let seconds = days * 86400 + time[0] * 3600 + time[1] * 60 + time[2];
+        return Some(UNIX_EPOCH + Duration::from_secs(seconds));
+    }
+
+    // the reason phrase sent after the status code , a generic one for the class of unknown codes
+    fn reason_phrase(status: i32) -> &'static str {
+        let phrase = match status {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            203 => "Non-Authoritative Information",
+            204 => "No Content",
+            205 => "Reset Content",
+            206 => "Partial Content",
+            300 => "Multiple Choices",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            402 => "Payment Required",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Content Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            417 => "Expectation Failed",
+            418 => "I'm a teapot",
+            422 => "Unprocessable Content",
+            426 => "Upgrade Required",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            451 => "Unavailable For Legal Reasons",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            _ => "",
+        };
+        if !phrase.is_empty() {
+            return phrase;
+        }
+        match status / 100 {
+            1 => "Informational",
+            2 => "Success",
+            3 => "Redirection",
+            4 => "Client Error",
+            5 => "Server Error",
+            _ => "Unknown",
+        }
+    }
+
+    // the headers `Response::pipe` doesn't forward besides `Connection` and `Transfer-Encoding` (RFC 9110 7.6.1)
+    const HOP_BY_HOP_HEADERS: [&str; 5] =
+        ["Keep-Alive", "Proxy-Connection", "TE", "Trailer", "Upgrade"];
+
+    // 1xx , 204 and 304 responses never have a body (nor a Content-Length) , whatever the handler set ,
+    // so the client knows the response ends with the headers and the next one can follow right away
+    fn status_allows_body(status: i32) -> bool {
+        return !(100..200).contains(&status) && status != 204 && status != 304;
+    }
+
+    // check that `text` is a single well formed JSON value , used to catch broken bodies in debug builds
+    fn is_valid_json(text: &str) -> bool {
+        let mut parser = JsonValidator {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        return parser.value(0) && {
+            parser.skip_whitespace();
+            parser.position == parser.bytes.len()
+        };
+    }
+
+    struct JsonValidator<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl JsonValidator<'_> {
+        const MAX_DEPTH: usize = 256;
+
+        fn peek(&self) -> Option<u8> {
+            self.bytes.get(self.position).copied()
+        }
+
+        fn skip_whitespace(&mut self) {
+            while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+                self.position += 1;
+            }
+        }
+
+        fn literal(&mut self, literal: &[u8]) -> bool {
+            if self.bytes[self.position..].starts_with(literal) {
+                self.position += literal.len();
+                return true;
+            }
+            return false;
+        }
+
+        fn value(&mut self, depth: usize) -> bool {
+            if depth > Self::MAX_DEPTH {
+                return false;
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'{') => self.object(depth),
+                Some(b'[') => self.array(depth),
+                Some(b'"') => self.string(),
+                Some(b't') => self.literal(b"true"),
+                Some(b'f') => self.literal(b"false"),
+                Some(b'n') => self.literal(b"null"),
+                Some(b'-' | b'0'..=b'9') => self.number(),
+                _ => false,
+            }
+        }
+
+        fn object(&mut self, depth: usize) -> bool {
+            self.position += 1;
+            self.skip_whitespace();
+            if self.peek() == Some(b'}') {
+                self.position += 1;
+                return true;
+            }
+            loop {
+                self.skip_whitespace();
+                if self.peek() != Some(b'"') || !self.string() {
+                    return false;
+                }
+                self.skip_whitespace();
+                if self.peek() != Some(b':') {
+                    return false;
+                }
+                self.position += 1;
+                if !self.value(depth + 1) {
+                    return false;
+                }
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.position += 1,
+                    Some(b'}') => {
+                        self.position += 1;
+                        return true;
+                    }
+                    _ => return false,
+                }
+            }
+        }
+
+        fn array(&mut self, depth: usize) -> bool {
+            self.position += 1;
+            self.skip_whitespace();
+            if self.peek() == Some(b']') {
+                self.position += 1;
+                return true;
+            }
+            loop {
+                if !self.value(depth + 1) {
+                    return false;
+                }
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => self.position += 1,
+                    Some(b']') => {
+                        self.position += 1;
+                        return true;
+                    }
+                    _ => return false,
+                }
+            }
+        }
+
+        fn string(&mut self) -> bool {
+            self.position += 1;
+            while let Some(byte) = self.peek() {
+                self.position += 1;
+                match byte {
+                    b'"' => return true,
+                    b'\\' => match self.peek() {
+                        Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                            self.position += 1
+                        }
+                        Some(b'u') => {
+                            let hex = self.bytes.get(self.position + 1..self.position + 5);
+                            match hex {
+                                Some(hex) if hex.iter().all(u8::is_ascii_hexdigit) => {
+                                    self.position += 5
+                                }
+                                _ => return false,
+                            }
+                        }
+                        _ => return false,
+                    },
+                    0x00..=0x1f => return false,
+                    _ => {}
+                }
+            }
+            return false;
+        }
+
+        fn number(&mut self) -> bool {
+            if self.peek() == Some(b'-') {
+                self.position += 1;
+            }
+            match self.peek() {
+                Some(b'0') => self.position += 1,
+                Some(b'1'..=b'9') => self.digits(),
+                _ => return false,
+            }
+            if self.peek() == Some(b'.') {
+                self.position += 1;
+                if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                    return false;
+                }
+                self.digits();
+            }
+            if let Some(b'e' | b'E') = self.peek() {
+                self.position += 1;
+                if let Some(b'+' | b'-') = self.peek() {
+                    self.position += 1;
+                }
+                if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                    return false;
+                }
+                self.digits();
+            }
+            return true;
+        }
+
+        fn digits(&mut self) {
+            while let Some(b'0'..=b'9') = self.peek() {
+                self.position += 1;
+            }
+        }
+    }
+
+    // read the file for the url `route` inside `root` , None when it is missing or outside of `root`
+    fn read_static_file(root: &std::path::Path, route: &str) -> Option<(&'static str, Vec<u8>)> {
+        let path = route.split('?').next().unwrap_or("");
+        let path = String::from_utf8(percent_decode(path.as_bytes(), false)).ok()?;
+        let root = root.canonicalize().ok()?;
+        // canonicalize resolves `..` and symlinks , what is left must still be inside the root
+        let file = root
+            .join(path.trim_start_matches('/'))
+            .canonicalize()
+            .ok()?;
+        if !file.starts_with(&root) || !file.is_file() {
+            return None;
+        }
+        let data = std::fs::read(&file).ok()?;
+        let extension = file.extension().and_then(|extension| extension.to_str());
+        return Some((content_type_for(extension.unwrap_or("")), data));
+    }
+
+    // guess the content type of a file from its extension
+    fn content_type_for(extension: &str) -> &'static str {
+        match extension.to_ascii_lowercase().as_str() {
+            "html" | "htm" => "text/html",
+            "css" => "text/css",
+            "js" | "mjs" => "text/javascript",
+            "json" => "application/json",
+            "txt" => "text/plain",
+            "csv" => "text/csv",
+            "xml" => "application/xml",
+            "svg" => "image/svg+xml",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "ico" => "image/x-icon",
+            "woff" => "font/woff",
+            "woff2" => "font/woff2",
+            "pdf" => "application/pdf",
+            "wasm" => "application/wasm",
+            "mp4" => "video/mp4",
+            _ => "application/octet-stream",
+        }
+    }
+
+    // remove `prefix` from `path` , only when it ends on a segment boundary
+    fn strip_path_prefix(path: &str, prefix: &str) -> Option<String> {
+        let rest = path.strip_prefix(prefix)?;
+        if rest.is_empty() {
+            return Some("/".to_string());
+        }
+        if rest.starts_with('/') {
+            return Some(rest.to_string());
+        }
+        return None;
+    }
+
+    #[derive(Debug)]
+    enum RouteSegment {
+        Static(String),
+        Dynamic(String),
+        // `*name` , the last segment , matching the rest of the path
+        Wildcard(String),
+    }
+
+    // the ID the client sent in the `name` header , or a new one when it sent none or an unusable one
+    fn request_id(request: &Request, name: &str) -> String {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        if let Some(id) = request.find_header(name) {
+            let id = id.trim();
+            if !id.is_empty() && id.len() <= 200 && id.bytes().all(|b| b.is_ascii_graphic()) {
+                return id.to_string();
+            }
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        let count = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        return format!("{:x}-{:x}-{:x}", now, std::process::id(), count);
+    }
+
+    // the segments of a dynamic route , None for a static route (without `:name` or `*name`) ,
+    // it panics when a wildcard isn't the last segment
+    fn route_segments(path: &str) -> Option<Vec<RouteSegment>> {
+        if !path.contains(':') && !path.contains('*') {
+            return None;
+        }
+        let mut vec = Vec::new();
+        for item in path.trim().split('/').filter(|s| !s.is_empty()) {
+            if let Some(name) = item.strip_prefix(':') {
+                vec.push(RouteSegment::Dynamic(name.to_string()))
+            } else if let Some(name) = item.strip_prefix('*') {
+                vec.push(RouteSegment::Wildcard(name.to_string()))
+            } else {
+                vec.push(RouteSegment::Static(item.to_string()));
+            }
+        }
+        let last = vec.len().saturating_sub(1);
+        if vec.iter().enumerate().any(|(position, segment)| {
+            position != last && matches!(segment, RouteSegment::Wildcard(_))
+        }) {
+            panic!("route {}: a wildcard can only be the last segment", path);
+        }
+        return Some(vec);
+    }
+
+    // the params captured when the path split in `parts` matches the dynamic route `segments`
+    fn match_segments(
+        segments: &[RouteSegment],
+        parts: &[&str],
+    ) -> Option<HashMap<String, String>> {
+        let wildcard = matches!(segments.last(), Some(RouteSegment::Wildcard(_)));
+        let fits = if wildcard {
+            parts.len() >= segments.len()
+        } else {
+            parts.len() == segments.len()
+        };
+        if !fits {
+            return None;
+        }
+        let mut params = HashMap::new();
+        for (position, (segment, part)) in segments.iter().zip(parts).enumerate() {
+            match segment {
+                RouteSegment::Static(s) => {
+                    if s != part {
+                        return None;
+                    }
+                }
+                RouteSegment::Dynamic(s) => {
+                    params.insert(s.to_string(), decode_component(part, false));
+                }
+                RouteSegment::Wildcard(s) => {
+                    let rest = parts[position..].join("/");
+                    params.insert(s.to_string(), decode_component(&rest, false));
+                }
+            }
+        }
+        return Some(params);
+    }
+
+    // the search params of a query string , None when it has more than `max_params` of them ,
+    // every pair counts towards the limit , repeated names and names without a value included
+    fn parse_query(query: &str, max_params: usize) -> Option<HashMap<String, String>> {
+        let mut search_params = HashMap::new();
+        let mut count = 0;
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            count += 1;
+            if count > max_params {
+                return None;
+            }
+            if let Some((name, value)) = param.split_once('=') {
+                search_params.insert(decode_component(name, true), decode_component(value, true));
+            }
+        }
+        return Some(search_params);
+    }
+
+    // the methods among `registered` that `matches` routes for a path , sorted , HEAD is checked too
+    // since it is allowed wherever GET is
+    fn collect_allowed_methods<'a>(
+        registered: impl Iterator<Item = &'a Method>,
+        matches: impl Fn(&Method) -> bool,
+    ) -> Vec<Method> {
+        let mut methods: Vec<Method> = Vec::new();
+        for method in registered.chain(std::iter::once(&Method::HEAD)) {
+            if !methods.contains(method) && matches(method) {
+                methods.push(method.clone());
+            }
+        }
+        methods.sort_by(|first, second| first.as_str().cmp(second.as_str()));
+        return methods;
+    }
+
+    // the 405 for a path routed for other methods , listing them in the `Allow` header
+    fn method_not_allowed(response: Response, allowed: &[Method]) -> Response {
+        let allow: Vec<&str> = allowed.iter().map(|method| method.as_str()).collect();
+        return response
+            .error(405, "Method Not Allowed")
+            .header("Allow", &allow.join(", "));
+    }
+
+    // true when `path` is served by a route registered as `route` , `:name` segments match any segment
+    // and a trailing `*name` the rest of the path
+    fn route_matches(route: &str, path: &str) -> bool {
+        if !route.contains(':') && !route.contains('*') {
+            return route == path;
+        }
+        let route: Vec<&str> = route.split('/').filter(|s| !s.is_empty()).collect();
+        let path: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let wildcard = route.last().is_some_and(|last| last.starts_with('*'));
+        let fits = if wildcard {
+            path.len() >= route.len()
+        } else {
+            path.len() == route.len()
+        };
+        return fits
+            && route.iter().zip(&path).all(|(route, path)| {
+                route.starts_with(':') || route.starts_with('*') || route == path
+            });
+    }
+
+    // two dynamic routes match the same paths when only the names of their params differ
+    fn same_route_shape(first: &[RouteSegment], second: &[RouteSegment]) -> bool {
+        return first.len() == second.len()
+            && first.iter().zip(second).all(|pair| match pair {
+                (RouteSegment::Static(first), RouteSegment::Static(second)) => first == second,
+                (RouteSegment::Dynamic(_), RouteSegment::Dynamic(_)) => true,
+                (RouteSegment::Wildcard(_), RouteSegment::Wildcard(_)) => true,
+                _ => false,
+            });
+    }
+
+    /// What happens when a method and route are registered a second time
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DuplicatePolicy {
+        /// The new handler replaces the old one and a warning is printed (the default)
+        LastWins,
+        /// Registering the route again panics , to catch the mistake at startup
+        Panic,
+    }
+
+    // which registered route matched a request
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum MatchedRoute {
+        // the method the static route is registered with , GET for a HEAD request
+        Static(Method),
+        // index into the dynamic routes , in registration order
+        Dynamic(usize),
+        // index into the mounted handlers , in registration order
+        Mount(usize),
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct MatchResult {
+        pub(crate) route: MatchedRoute,
+        pub(crate) params: HashMap<String, String>,
+    }
+
+    /// A Handler turns a [Request] into a [Response]
+    ///
+    /// It is implemented for every `Fn(&Request, Response) -> Response` closure that is `Send + Sync` ,
+    /// handlers are shared by the threads serving the connections ,
+    /// implement it on your own types to register handlers that hold state with [Application::handle]
+    ///
+    /// # Example:
+    /// ```rust
+    /// use express_rs::express::{Application, Handler, Method, Request, Response};
+    ///
+    /// struct Greeter {
+    ///     greeting: String,
+    /// }
+    ///
+    /// impl Handler for Greeter {
+    ///     fn handle(&self, _request: &Request, response: Response) -> Response {
+    ///         response.status(200).html(self.greeting.clone())
+    ///     }
+    /// }
+    ///
+    /// let mut app = Application::new();
+    /// app.handle(Method::GET, "/hello".to_string(), Greeter { greeting: "Hello".to_string() });
+    /// ```
+    pub trait Handler: Send + Sync {
+        fn handle(&self, request: &Request, response: Response) -> Response;
+    }
+
+    impl<F> Handler for F
+    where
+        F: Fn(&Request, Response) -> Response + Send + Sync,
+    {
+        fn handle(&self, request: &Request, response: Response) -> Response {
+            return self(request, response);
+        }
+    }
+
+    type RouteFunction = dyn Handler + 'static;
+
+    type ErrorFunction = dyn Fn(&Request, &str, Response) -> Response + Send + Sync + 'static;
+    type RewriteFunction = dyn Fn(&mut Request) + Send + Sync + 'static;
+    type MiddlewareFunction =
+        dyn Fn(&mut Request, &mut Response) -> MiddlewareResult + Send + Sync + 'static;
+
+    /// What a middleware registered with [Application::use_middleware] wants to happen next
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MiddlewareResult {
+        /// Run the next middleware , then the matched route
+        Next,
+        /// Send the response as it is now and skip the rest of the chain and the route
+        Halt,
+    }
+
+    // extract the message given to `panic!` from a panic payload
+    fn panic_message(payload: &(dyn Any + Send)) -> String {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            return message.to_string();
+        }
+        if let Some(message) = payload.downcast_ref::<String>() {
+            return message.clone();
+        }
+        return "unknown error".to_string();
+    }
+
+    thread_local! {
+        // true while the thread serves a connection of a quiet application
+        static QUIET_PANICS: Cell<bool> = const { Cell::new(false) };
+    }
+
+    static QUIET_PANIC_HOOK: Once = Once::new();
+
+    // run `function` catching its panic , when `quiet` the default panic message isn't printed ,
+    // the hook doing that is installed once and keeps the previous hook for every other panic
+    fn catch_panic<R>(quiet: bool, function: impl FnOnce() -> R) -> std::thread::Result<R> {
+        if quiet {
+            QUIET_PANIC_HOOK.call_once(|| {
+                let previous = panic::take_hook();
+                panic::set_hook(Box::new(move |info| {
+                    if !QUIET_PANICS.with(|quiet| quiet.get()) {
+                        previous(info);
+                    }
+                }));
+            });
+        }
+        let outer = QUIET_PANICS.with(|flag| flag.replace(quiet));
+        let result = panic::catch_unwind(AssertUnwindSafe(function));
+        QUIET_PANICS.with(|flag| flag.set(outer));
+        return result;
+    }
+
+    /// The Application struct is responsible for handling incoming requests and routing them to the appropriate handler function
+    pub struct Application {
+        static_methods: HashMap<(Method, String), Box<RouteFunction>>,
+        dynamic_methods: Vec<(Method, Vec<RouteSegment>, Box<RouteFunction>)>,
+        mounts: Vec<(String, Box<RouteFunction>)>,
+        keep_alive: bool,
+        keep_alive_timeout: Duration,
+        // how long a kept alive connection may wait for its next request , None for `keep_alive_timeout`
+        keep_alive_idle: Option<Duration>,
+        // how long a request may take to arrive once its first byte was received
+        request_timeout: Duration,
+        keep_alive_max_requests: usize,
+        default_index: bool,
+        strip_prefix: Option<String>,
+        trust_proxy: bool,
+        max_params: usize,
+        json_errors: bool,
+        json_limit: Option<usize>,
+        max_body_size: Option<usize>,
+        strict_headers: bool,
+        server_timing: bool,
+        error_handler: Option<Box<ErrorFunction>>,
+        not_found: Option<Box<RouteFunction>>,
+        cors: Option<CorsConfig>,
+        before_route: Option<Box<RewriteFunction>>,
+        middleware: Vec<Box<MiddlewareFunction>>,
+        // middleware running after the global one for the paths under a prefix
+        group_middleware: Vec<(String, Box<MiddlewareFunction>)>,
+        shutdown: ShutdownHandle,
+        #[cfg(feature = "tls")]
+        tls: Option<Arc<rustls::ServerConfig>>,
+        nosniff: bool,
+        quiet: bool,
+        // where the startup lines go , stdout unless `log_sink` replaced it
+        log_sink: Mutex<Box<dyn Write + Send>>,
+        max_connections_per_ip: Option<usize>,
+        connections_per_ip: ConnectionCounter,
+        overload_policy: OverloadPolicy,
+        // size of the worker pool , None for a thread per connection
+        workers: Option<usize>,
+        duplicate_policy: DuplicatePolicy,
+        // the routes marked by `with_request_body_required`
+        body_required: Vec<(Method, String)>,
+        // the header carrying request IDs , None when they aren't tracked
+        request_id_header: Option<String>,
+        secure_cookies: bool,
+        // the smallest body compressed , None when compression is disabled
+        #[cfg(feature = "gzip")]
+        gzip_min_size: Option<usize>,
+    }
+
+    /// What happens to a new connection when a connection limit is reached
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum OverloadPolicy {
+        /// Answer it with a 503 and close it right away (the default)
+        Reject,
+        /// Wait for a slot before serving it , the server keeps accepting other connections meanwhile
+        Queue,
+    }
+
+    /// The origins allowed to call the application from a browser , see [CorsConfig]
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub enum AllowedOrigins {
+        /// Any origin , sent as `Access-Control-Allow-Origin: *` (the default)
+        #[default]
+        Any,
+        /// Only these origins (`https://example.com` for example) , others get no CORS headers
+        List(Vec<String>),
+    }
+
+    /// The CORS settings enabled with [Application::cors]
+    ///
+    /// Leaving `methods` empty allows the methods routed for the requested path ,
+    /// leaving `headers` empty allows the headers the preflight asks for
+    #[derive(Debug, Clone, Default)]
+    pub struct CorsConfig {
+        pub origins: AllowedOrigins,
+        pub methods: Vec<Method>,
+        pub headers: Vec<String>,
+        /// How long browsers may cache the answer to a preflight
+        pub max_age: Option<Duration>,
+    }
+
+    // counts the open connections of every client IP , shared by the threads serving them
+    #[derive(Clone, Default)]
+    struct ConnectionCounter {
+        counts: Arc<Mutex<HashMap<IpAddr, usize>>>,
+        // notified every time a slot is given back
+        released: Arc<Condvar>,
+    }
+
+    impl ConnectionCounter {
+        // take a connection slot for `ip` , None when it already has `limit` open connections
+        fn acquire(&self, ip: IpAddr, limit: usize) -> Option<ConnectionSlot> {
+            let mut counts = self.counts.lock().unwrap();
+            let count = counts.entry(ip).or_insert(0);
+            if *count >= limit {
+                return None;
+            }
+            *count += 1;
+            return Some(ConnectionSlot {
+                counter: self.clone(),
+                ip,
+            });
+        }
+
+        // take a connection slot for `ip` , waiting until one is free
+        fn acquire_waiting(&self, ip: IpAddr, limit: usize) -> ConnectionSlot {
+            let mut counts = self.counts.lock().unwrap();
+            while counts.get(&ip).is_some_and(|count| *count >= limit) {
+                counts = self.released.wait(counts).unwrap();
+            }
+            *counts.entry(ip).or_insert(0) += 1;
+            return ConnectionSlot {
+                counter: self.clone(),
+                ip,
+            };
+        }
+    }
+
+    // the slot a connection may be served with , or the error it is rejected with
+    type ConnectionAdmission = Result<Option<ConnectionSlot>, ErrorResponse>;
+
+    // gives the slot back to the counter when the connection is dropped
+    struct ConnectionSlot {
+        counter: ConnectionCounter,
+        ip: IpAddr,
+    }
+
+    impl Drop for ConnectionSlot {
+        fn drop(&mut self) {
+            let mut counts = self.counter.counts.lock().unwrap();
+            if let Some(count) = counts.get_mut(&self.ip) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&self.ip);
+                }
+            }
+            self.counter.released.notify_all();
+        }
+    }
+
+    // the connection to a client , a plain TCP socket or a TLS session over one
+    enum Stream {
+        Plain(TcpStream),
+        #[cfg(feature = "tls")]
+        Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+    }
+
+    impl Stream {
+        fn is_secure(&self) -> bool {
+            match self {
+                Stream::Plain(_) => false,
+                #[cfg(feature = "tls")]
+                Stream::Tls(_) => true,
+            }
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            match self {
+                Stream::Plain(stream) => stream.set_read_timeout(timeout),
+                #[cfg(feature = "tls")]
+                Stream::Tls(stream) => stream.sock.set_read_timeout(timeout),
+            }
+        }
+
+        // tell a TLS client the session is over before the socket is dropped
+        fn close(&mut self) {
+            #[cfg(feature = "tls")]
+            if let Stream::Tls(stream) = self {
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
+            }
+        }
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self {
+                Stream::Plain(stream) => stream.read(buf),
+                #[cfg(feature = "tls")]
+                Stream::Tls(stream) => stream.read(buf),
+            }
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            match self {
+                Stream::Plain(stream) => stream.write(buf),
+                #[cfg(feature = "tls")]
+                Stream::Tls(stream) => stream.write(buf),
+            }
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            match self {
+                Stream::Plain(stream) => stream.flush(),
+                #[cfg(feature = "tls")]
+                Stream::Tls(stream) => stream.flush(),
+            }
+        }
+    }
+
+    /// A handle used to stop a running [Application] from another thread
+    ///
+    /// The accept loop stops after the connection being served (if any) is finished , and `listen` returns
+    ///
+    /// # Example:
+    /// ```rust
+    /// # use express_rs::express;
+    /// use std::sync::mpsc;
+    ///
+    /// let mut app = express::Application::new();
+    /// let handle = app.shutdown_handle();
+    /// let (ready, started) = mpsc::channel();
+    /// let server = std::thread::spawn(move || app.listen_with_ready(0, ready));
+    /// started.recv().unwrap();
+    /// handle.shutdown();
+    /// // listen returned
+    /// server.join().unwrap().unwrap();
+    /// ```
+    #[derive(Clone, Default)]
+    pub struct ShutdownHandle {
+        state: Arc<ShutdownState>,
+    }
+
+    #[derive(Default)]
+    struct ShutdownState {
+        requested: AtomicBool,
+        // every address the application listens on , `listen_many` has several
+        addresses: Mutex<Vec<SocketAddr>>,
+    }
+
+    impl ShutdownHandle {
+        /// Ask the server to stop accepting connections
+        pub fn shutdown(&self) {
+            self.state.requested.store(true, Ordering::SeqCst);
+            // the accept loop is blocked waiting for a connection , connect to wake it up
+            for mut address in self.state.addresses.lock().unwrap().iter().copied() {
+                if address.ip().is_unspecified() {
+                    match address {
+                        SocketAddr::V4(_) => address.set_ip(Ipv4Addr::LOCALHOST.into()),
+                        SocketAddr::V6(_) => address.set_ip(Ipv6Addr::LOCALHOST.into()),
+                    }
+                }
+                let _ = TcpStream::connect(address);
+            }
+        }
+
+        /// Returns true once [ShutdownHandle::shutdown] was called
+        pub fn is_requested(&self) -> bool {
+            return self.state.requested.load(Ordering::SeqCst);
+        }
+
+        fn listening_on(&self, address: SocketAddr) {
+            self.state.addresses.lock().unwrap().push(address);
+        }
+    }
+
+    // the applications to stop when the process gets SIGINT / SIGTERM , see `graceful_on_signals` ,
+    // weak so a dropped application isn't kept alive
+    #[cfg(feature = "signals")]
+    static SIGNAL_HANDLES: Mutex<Vec<std::sync::Weak<ShutdownState>>> = Mutex::new(Vec::new());
+
+    // register `handle` to be stopped by SIGINT / SIGTERM , the handler itself is installed once per process
+    #[cfg(feature = "signals")]
+    fn shutdown_on_signals(handle: &ShutdownHandle) -> Result<(), ctrlc::Error> {
+        static INSTALLED: Mutex<bool> = Mutex::new(false);
+        let mut installed = INSTALLED.lock().unwrap();
+        if !*installed {
+            // the handler runs on a thread of its own , not in the signal context , so it can stop the servers directly
+            ctrlc::set_handler(shutdown_signalled)?;
+            *installed = true;
+        }
+        SIGNAL_HANDLES
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&handle.state));
+        return Ok(());
+    }
+
+    // stop every application registered with `graceful_on_signals`
+    #[cfg(feature = "signals")]
+    fn shutdown_signalled() {
+        let handles = std::mem::take(&mut *SIGNAL_HANDLES.lock().unwrap());
+        for state in handles.iter().filter_map(std::sync::Weak::upgrade) {
+            ShutdownHandle { state }.shutdown();
+        }
+    }
+
+    impl Application {
+        pub fn get<F>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.add_new_route(route, Method::GET, Box::new(function));
+        }
+        pub fn post<F>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.add_new_route(route, Method::POST, Box::new(function));
+        }
+        pub fn put<F>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.add_new_route(route, Method::PUT, Box::new(function));
+        }
+        pub fn patch<F>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.add_new_route(route, Method::PATCH, Box::new(function));
+        }
+        pub fn delete<F>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.add_new_route(route, Method::DELETE, Box::new(function));
+        }
+        /// Handle every request under `prefix` (any method) with a single function
+        ///
+        /// The function sees `request.route` without the prefix , `/admin/dashboard` becomes `/dashboard` \
+        /// Routes registered on the application are matched first
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.mount_fn("/admin", |request, response| match request.route.as_str() {
+        ///     "/dashboard" => response.status(200).html("<h1>Dashboard</h1>".to_string()),
+        ///     _ => response.status(404),
+        /// });
+        /// ```
+        pub fn mount_fn<F>(&mut self, prefix: &str, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            let prefix = prefix.trim_end_matches('/').to_string();
+            self.mounts.push((prefix, Box::new(function)));
+        }
+        /// Serve the files of the `fs_path` directory for GET and HEAD requests under `url_prefix`
+        ///
+        /// `/assets/css/style.css` is read from `<fs_path>/css/style.css` , the content type is guessed from the extension \
+        /// Missing files get a 404 , paths escaping `fs_path` (`/assets/../../etc/passwd`) are refused with a 404 too
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.static_dir("/assets", "./public");
+        /// ```
+        pub fn static_dir(&mut self, url_prefix: &str, fs_path: &str) {
+            let root = std::path::PathBuf::from(fs_path);
+            self.mount_fn(url_prefix, move |request, response| {
+                if request.method != Method::GET && request.method != Method::HEAD {
+                    return method_not_allowed(response, &[Method::GET, Method::HEAD]);
+                }
+                match read_static_file(&root, &request.route) {
+                    Some((content_type, data)) => response.status(200).bytes(data, content_type),
+                    None => response.status(404),
+                }
+            });
+        }
+        /// Registers any [Handler] for the given method and route
+        pub fn handle<H>(&mut self, method: Method, route: String, handler: H)
+        where
+            H: Handler + 'static,
+        {
+            self.add_new_route(route, method, Box::new(handler));
+        }
+
+        /// Require a body on a route , requests without one are answered before the handler runs
+        ///
+        /// A request without `Content-Length` (or `Transfer-Encoding`) gets a 411 Length Required ,
+        /// an empty body gets a 400 \
+        /// `route` is written like when the route was registered , `/users/:id` for example
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::Method;
+        ///
+        /// let mut app = express::Application::new();
+        /// app.post("/users".to_string(), |request, response| response.status(201));
+        /// app.with_request_body_required(Method::POST, "/users");
+        /// ```
+        pub fn with_request_body_required(&mut self, method: Method, route: &str) {
+            self.body_required.push((method, route.trim().to_string()));
+        }
+    }
+
+    impl Default for Application {
+        fn default() -> Self {
+            return Application::new();
+        }
+    }
+
+    impl Application {
+        // create a new application
+        pub fn new() -> Application {
+            return Application {
+                static_methods: HashMap::new(),
+                dynamic_methods: Vec::new(),
+                mounts: Vec::new(),
+                keep_alive: true,
+                keep_alive_timeout: Duration::from_secs(5),
+                keep_alive_idle: None,
+                request_timeout: Duration::from_secs(30),
+                keep_alive_max_requests: 100,
+                default_index: false,
+                strip_prefix: None,
+                trust_proxy: false,
+                max_params: 1000,
+                json_errors: false,
+                json_limit: None,
+                max_body_size: Some(DEFAULT_MAX_BODY_SIZE),
+                strict_headers: false,
+                server_timing: false,
+                error_handler: None,
+                not_found: None,
+                cors: None,
+                before_route: None,
+                middleware: Vec::new(),
+                group_middleware: Vec::new(),
+                shutdown: ShutdownHandle::default(),
+                #[cfg(feature = "tls")]
+                tls: None,
+                nosniff: false,
+                quiet: false,
+                log_sink: Mutex::new(Box::new(std::io::stdout())),
+                max_connections_per_ip: None,
+                connections_per_ip: ConnectionCounter::default(),
+                overload_policy: OverloadPolicy::Reject,
+                workers: None,
+                duplicate_policy: DuplicatePolicy::LastWins,
+                body_required: Vec::new(),
+                request_id_header: None,
+                secure_cookies: true,
+                #[cfg(feature = "gzip")]
+                gzip_min_size: Some(1024),
+            };
+        }
+
+        /// Enable or disable keep-alive connections (enabled by default)
+        ///
+        /// When enabled a connection serves successive requests (pipelined ones too) until the client
+        /// sends `Connection: close` or closes it , responses say `Connection: keep-alive` while it stays open \
+        /// When disabled every response is sent with `Connection: close` and the socket
+        /// is closed after one response, regardless of what the client asked for
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use std::io::{Read, Write};
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.quiet(true);
+        ///     app.get("/".to_string(), |request, response| response.text("hi".to_string()));
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        ///
+        /// // two requests on one connection , the second one closes it
+        /// let mut stream = std::net::TcpStream::connect(address).unwrap();
+        /// stream.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        /// let mut answer = String::new();
+        /// stream.read_to_string(&mut answer).unwrap();
+        /// assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 2);
+        /// assert_eq!(answer.matches("Connection: keep-alive").count(), 1);
+        /// assert!(answer.contains("Connection: close"));
+        /// ```
+        pub fn enable_keep_alive(&mut self, enabled: bool) {
+            self.keep_alive = enabled;
+        }
+
+        /// Set how long an idle keep-alive connection is kept open (5 seconds by default)
+        ///
+        /// The value is advertised to clients in the `Keep-Alive: timeout=..` response header
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.keep_alive_timeout(std::time::Duration::from_secs(10));
+        /// ```
+        pub fn keep_alive_timeout(&mut self, timeout: Duration) {
+            self.keep_alive_timeout = timeout;
+        }
+
+        /// Set how long a keep-alive connection may stay idle waiting for its next request before the server closes it
+        ///
+        /// A new connection gets the same time to start sending its first request \
+        /// It defaults to the advertised [Application::keep_alive_timeout] , a longer limit gives slow clients some slack \
+        /// It only covers the wait for the first byte of the next request , a request that started arriving isn't cut by it
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.max_keepalive_idle(std::time::Duration::from_secs(30));
+        /// ```
+        pub fn max_keepalive_idle(&mut self, idle: Duration) {
+            self.keep_alive_idle = Some(idle);
+        }
+
+        /// Set how long a request (its headers and body) may take to arrive once it started arriving (30 seconds by default)
+        ///
+        /// Slower requests get a 408 response and the connection is closed
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.request_timeout(std::time::Duration::from_secs(10));
+        /// ```
+        pub fn request_timeout(&mut self, timeout: Duration) {
+            self.request_timeout = timeout;
+        }
+
+        /// Set how many requests can be served on one connection (100 by default)
+        ///
+        /// The remaining count is advertised to clients in the `Keep-Alive: max=..` response header ,
+        /// the response to the last allowed request is sent with `Connection: close`
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.keep_alive_max_requests(50);
+        /// ```
+        pub fn keep_alive_max_requests(&mut self, max: usize) {
+            self.keep_alive_max_requests = max.max(1);
+        }
+
+        /// Serve a minimal "express-rs is running" page on `GET /` when no route is registered for it
+        ///
+        /// It is disabled by default so it doesn't mask real 404s
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.default_index(true);
+        /// ```
+        pub fn default_index(&mut self, enabled: bool) {
+            self.default_index = enabled;
+        }
+
+        /// Serve HTTPS using the PEM encoded certificate chain and private key files (requires the `tls` feature)
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.with_tls("cert.pem", "key.pem").unwrap();
+        /// app.listen(8443);
+        /// ```
+        #[cfg(feature = "tls")]
+        pub fn with_tls(&mut self, cert_path: &str, key_path: &str) -> std::io::Result<()> {
+            let cert = std::fs::read(cert_path)?;
+            let key = std::fs::read(key_path)?;
+            return self.with_tls_from_pem_bytes(&cert, &key);
+        }
+
+        /// Serve HTTPS using a PEM encoded certificate chain and private key held in memory (requires the `tls` feature)
+        ///
+        /// Useful when certificates come from a secrets manager instead of the disk
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// # let (cert, key) = (Vec::new(), Vec::new());
+        /// let mut app = express::Application::new();
+        /// app.with_tls_from_pem_bytes(&cert, &key).unwrap();
+        /// app.listen(8443);
+        /// ```
+        #[cfg(feature = "tls")]
+        pub fn with_tls_from_pem_bytes(&mut self, cert: &[u8], key: &[u8]) -> std::io::Result<()> {
+            use rustls::pki_types::pem::PemObject;
+            use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+            let invalid =
+                |error: String| std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+
+            let certs = CertificateDer::pem_slice_iter(cert)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| invalid(format!("invalid certificate: {}", error)))?;
+            if certs.is_empty() {
+                return Err(invalid("no certificate found".to_string()));
+            }
+            let key = PrivateKeyDer::from_pem_slice(key)
+                .map_err(|error| invalid(format!("invalid private key: {}", error)))?;
+
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+                .map_err(|error| invalid(error.to_string()))?;
+
+            self.tls = Some(Arc::new(config));
+            return Ok(());
+        }
+
+        /// Stop printing the `Started server on ...` line when the server starts (disabled by default)
+        ///
+        /// The duplicate route warnings and the messages of panicking handlers (answered with a 500) aren't printed either ,
+        /// useful for libraries and tests that embed the server
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use std::io::Write;
+        /// use std::sync::{Arc, Mutex, mpsc};
+        ///
+        /// #[derive(Clone, Default)]
+        /// struct Captured(Arc<Mutex<Vec<u8>>>);
+        ///
+        /// impl Write for Captured {
+        ///     fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        ///         self.0.lock().unwrap().write(bytes)
+        ///     }
+        ///     fn flush(&mut self) -> std::io::Result<()> {
+        ///         Ok(())
+        ///     }
+        /// }
+        ///
+        /// let captured = Captured::default();
+        /// let sink = captured.clone();
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.log_sink(sink);
+        ///     app.quiet(true);
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// started.recv().unwrap();
+        /// assert!(captured.0.lock().unwrap().is_empty());
+        /// ```
+        pub fn quiet(&mut self, enabled: bool) {
+            self.quiet = enabled;
+        }
+
+        /// Write the lines the server prints (the `Started server on ...` line and the duplicate route warnings) to `sink` instead of stdout
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.log_sink(std::io::stderr());
+        /// ```
+        pub fn log_sink<W>(&mut self, sink: W)
+        where
+            W: Write + Send + 'static,
+        {
+            self.log_sink = Mutex::new(Box::new(sink));
+        }
+
+        // print a line to the log sink , unless the application is quiet
+        fn log(&self, message: &str) {
+            if self.quiet {
+                return;
+            }
+            if let Ok(mut sink) = self.log_sink.lock() {
+                let _ = writeln!(sink, "{}", message);
+            }
+        }
+
+        /// Send `X-Content-Type-Options: nosniff` with every response that has a content type (disabled by default)
+        ///
+        /// This stops browsers from guessing a different type than the one declared
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.nosniff(true);
+        /// ```
+        pub fn nosniff(&mut self, enabled: bool) {
+            self.nosniff = enabled;
+        }
+
+        /// Limit how many connections a single client IP can have open at the same time (unlimited by default)
+        ///
+        /// Connections over the limit get a 503 response and are closed right away ,
+        /// unless [Application::overload_policy] is set to [OverloadPolicy::Queue]
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.max_connections_per_ip(10);
+        /// ```
+        pub fn max_connections_per_ip(&mut self, max: usize) {
+            self.max_connections_per_ip = Some(max);
+        }
+
+        /// Choose what happens to connections over the [Application::max_connections_per_ip] limit
+        /// ([OverloadPolicy::Reject] by default)
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.max_connections_per_ip(10);
+        /// app.overload_policy(express::OverloadPolicy::Queue);
+        /// ```
+        pub fn overload_policy(&mut self, policy: OverloadPolicy) {
+            self.overload_policy = policy;
+        }
+
+        /// Choose what happens when a method and route are registered twice ([DuplicatePolicy::LastWins] by default)
+        ///
+        /// Dynamic routes only differing by the names of their params (`/users/:id` and `/users/:name`) are duplicates too
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.on_duplicate_route(express::DuplicatePolicy::Panic);
+        /// app.get("/x".to_string(), |request, response| response.status(200));
+        /// ```
+        pub fn on_duplicate_route(&mut self, policy: DuplicatePolicy) {
+            self.duplicate_policy = policy;
+        }
+
+        /// Trust the `X-Forwarded-*` headers set by a reverse proxy in front of the server (disabled by default)
+        ///
+        /// When enabled [Request::scheme] uses `X-Forwarded-Proto` and [Request::full_url] uses `X-Forwarded-Host`
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.trust_proxy(true);
+        /// ```
+        pub fn trust_proxy(&mut self, enabled: bool) {
+            self.trust_proxy = enabled;
+        }
+
+        /// Set the maximum number of path and query params captured for a request (1000 by default)
+        ///
+        /// Requests with more params than the limit get a 400 response , every `&` separated query pair counts (repeated names and names without a value too)
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.max_params(20);
+        /// ```
+        pub fn max_params(&mut self, max: usize) {
+            self.max_params = max;
+        }
+
+        /// Send error bodies as JSON (`{"error": "<message>"}`) instead of plain text (disabled by default)
+        ///
+        /// Applies to the errors the server answers by itself and to [Response::error]
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.json_errors(true);
+        /// ```
+        pub fn json_errors(&mut self, enabled: bool) {
+            self.json_errors = enabled;
+        }
+
+        /// Set the maximum size in bytes of `application/json` request bodies (no limit by default)
+        ///
+        /// The content type is compared without case and parameters , see [Request::content_type_essence]
+        ///
+        /// Larger bodies get a 413 response as soon as their headers are read , the body itself is never read
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.json_limit(100 * 1024);
+        /// ```
+        pub fn json_limit(&mut self, bytes: usize) {
+            self.json_limit = Some(bytes);
+        }
+
+        /// Set the maximum size in bytes of request bodies of any type (10 MiB by default)
+        ///
+        /// Larger bodies get a 413 response as soon as their headers are read , the body itself is never read \
+        /// Pass `None` to accept bodies of any size
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.max_body_size(Some(50 * 1024 * 1024));
+        /// ```
+        pub fn max_body_size(&mut self, bytes: Option<usize>) {
+            self.max_body_size = bytes;
+        }
+
+        /// Enable CORS , so browser pages from other origins can call the application
+        ///
+        /// OPTIONS preflight requests are answered with a 204 listing the allowed methods and headers
+        /// (unless an OPTIONS route is registered for the path) ,
+        /// every response gets `Access-Control-Allow-Origin` when the request origin is allowed
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::{AllowedOrigins, CorsConfig, Method};
+        ///
+        /// let mut app = express::Application::new();
+        /// app.cors(CorsConfig {
+        ///     origins: AllowedOrigins::List(vec!["https://example.com".to_string()]),
+        ///     methods: vec![Method::GET, Method::POST],
+        ///     headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+        ///     ..Default::default()
+        /// });
+        /// ```
+        pub fn cors(&mut self, config: CorsConfig) {
+            self.cors = Some(config);
+        }
+
+        /// Give every request an ID , read from the `name` header and sent back in it (disabled by default)
+        ///
+        /// An ID sent by the client is kept , so it can be followed through several services ,
+        /// otherwise one is generated. Handlers read it with [Request::request_id]
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use std::io::{Read, Write};
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.quiet(true);
+        ///     app.request_id_header("X-Correlation-Id");
+        ///     app.get("/".to_string(), |request, response| {
+        ///         response.text(request.request_id().unwrap().to_string())
+        ///     });
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        ///
+        /// let mut stream = std::net::TcpStream::connect(address).unwrap();
+        /// stream.write_all(b"GET / HTTP/1.1\r\nX-Correlation-Id: abc-123\r\nConnection: close\r\n\r\n").unwrap();
+        /// let mut answer = String::new();
+        /// stream.read_to_string(&mut answer).unwrap();
+        /// assert!(answer.contains("X-Correlation-Id: abc-123\r\n"));
+        /// assert!(answer.ends_with("abc-123"));
+        /// assert!(!answer.contains("X-Request-Id"));
+        /// ```
+        pub fn request_id_header(&mut self, name: &str) {
+            self.request_id_header = Some(name.to_string());
+        }
+
+        /// Gzip response bodies of at least `min_size` bytes for clients accepting it (1024 bytes by default) ,
+        /// `None` disables compression. Requires the `gzip` feature
+        ///
+        /// Text , JSON , JavaScript and XML bodies are compressed when `Accept-Encoding` lists `gzip` ,
+        /// streamed and [Response::no_transform] responses are left untouched
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use std::io::{Read, Write};
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.quiet(true);
+        ///     app.compression(Some(100));
+        ///     app.get("/".to_string(), |request, response| response.text("hello ".repeat(100)));
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        ///
+        /// let mut stream = std::net::TcpStream::connect(address).unwrap();
+        /// stream.write_all(b"GET / HTTP/1.1\r\nAccept-Encoding: gzip, br\r\nConnection: close\r\n\r\n").unwrap();
+        /// let mut answer = Vec::new();
+        /// stream.read_to_end(&mut answer).unwrap();
+        /// let end = answer.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+        /// let head = String::from_utf8_lossy(&answer[..end]).to_string();
+        /// assert!(head.contains("Content-Encoding: gzip\r\n"));
+        /// assert!(head.contains(&format!("Content-Length: {}\r\n", answer.len() - end)));
+        ///
+        /// let mut body = String::new();
+        /// flate2::read::GzDecoder::new(&answer[end..]).read_to_string(&mut body).unwrap();
+        /// assert_eq!(body, "hello ".repeat(100));
+        /// ```
+        #[cfg(feature = "gzip")]
+        pub fn compression(&mut self, min_size: Option<usize>) {
+            self.gzip_min_size = min_size;
+        }
+
+        /// Give cookies the `Secure` flag on requests made over https (enabled by default)
+        ///
+        /// Cookies set on plain http requests don't get it , so local development still works ,
+        /// the `secure` field of [CookieOptions] overrides this for one cookie \
+        /// Behind a proxy the request counts as https when [Application::trust_proxy] is enabled
+        /// and the proxy sends `X-Forwarded-Proto: https`
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::CookieOptions;
+        /// use std::io::{Read, Write};
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.quiet(true);
+        ///     app.trust_proxy(true);
+        ///     app.secure_cookies(true);
+        ///     app.get("/".to_string(), |request, response| {
+        ///         response.cookie("session", "abc", CookieOptions::default())
+        ///     });
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        ///
+        /// let set_cookie = |extra: &str| {
+        ///     let mut stream = std::net::TcpStream::connect(address).unwrap();
+        ///     let request = format!("GET / HTTP/1.1\r\n{}Connection: close\r\n\r\n", extra);
+        ///     stream.write_all(request.as_bytes()).unwrap();
+        ///     let mut answer = String::new();
+        ///     stream.read_to_string(&mut answer).unwrap();
+        ///     answer.lines().find(|line| line.starts_with("Set-Cookie")).unwrap().to_string()
+        /// };
+        /// assert!(set_cookie("X-Forwarded-Proto: https\r\n").ends_with("; Secure"));
+        /// assert!(!set_cookie("").contains("Secure"));
+        /// ```
+        pub fn secure_cookies(&mut self, enabled: bool) {
+            self.secure_cookies = enabled;
+        }
+
+        /// Reject requests whose headers are not valid UTF-8 with a 400 (disabled by default)
+        ///
+        /// Without strict mode invalid bytes are replaced with U+FFFD
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.strict_headers(true);
+        /// ```
+        pub fn strict_headers(&mut self, enabled: bool) {
+            self.strict_headers = enabled;
+        }
+
+        /// Add a `Server-Timing: app;dur=<milliseconds>` header with the handler duration to every response (disabled by default)
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.server_timing(true);
+        /// ```
+        pub fn server_timing(&mut self, enabled: bool) {
+            self.server_timing = enabled;
+        }
+
+        /// Returns a [ShutdownHandle] that stops this application's accept loop when triggered
+        pub fn shutdown_handle(&self) -> ShutdownHandle {
+            return self.shutdown.clone();
+        }
+
+        /// Stop the server gracefully on SIGINT / SIGTERM (Ctrl-C on Windows) , requires the `signals` feature
+        ///
+        /// The connection being served is finished and then `listen` returns , instead of the process being killed \
+        /// Every application registered in the process is stopped by the signal , the handler is installed once (with the `ctrlc` crate) ,
+        /// an error is returned when the process already has a `ctrlc` handler of its own
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.graceful_on_signals().unwrap();
+        /// app.listen(8080);
+        /// println!("server stopped");
+        /// ```
+        #[cfg(feature = "signals")]
+        pub fn graceful_on_signals(&mut self) -> Result<(), ctrlc::Error> {
+            return shutdown_on_signals(&self.shutdown);
+        }
+
+        /// Register a function called when a handler panics
+        ///
+        /// The server keeps running and answers the failed request with a 500 ,
+        /// the function receives the request , the panic message and that 500 response to customize
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.on_error(|request, message, response| {
+        ///     eprintln!("{} failed: {}", request.route, message);
+        ///     response.json(r#"{"error": "Internal Server Error"}"#.to_string())
+        /// });
+        /// ```
+        pub fn on_error<F>(&mut self, function: F)
+        where
+            F: Fn(&Request, &str, Response) -> Response + Send + Sync + 'static,
+        {
+            self.error_handler = Some(Box::new(function));
+        }
+
+        /// Register a function that answers the requests no route matches
+        ///
+        /// It receives the request and a response with the 404 status already set ,
+        /// without it a 404 with a short plain text body is sent
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.not_found(|request, response| {
+        ///     response.html(format!("<h1>{} doesn't exist</h1>", request.route))
+        /// });
+        /// ```
+        pub fn not_found<F>(&mut self, function: F)
+        where
+            F: Fn(&Request, Response) -> Response + Send + Sync + 'static,
+        {
+            self.not_found = Some(Box::new(function));
+        }
+
+        /// Register a function that can change each request before it is matched against the routes
+        ///
+        /// Rewriting `request.route` serves another route internally , the client isn't redirected
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.before_route(|request| {
+        ///     if request.route == "/old" {
+        ///         request.route = "/new".to_string();
+        ///     }
+        /// });
+        /// app.get("/new".to_string(), |request, response| response.status(200));
+        /// ```
+        pub fn before_route<F>(&mut self, function: F)
+        where
+            F: Fn(&mut Request) + Send + Sync + 'static,
+        {
+            self.before_route = Some(Box::new(function));
+        }
+
+        /// Register a middleware that runs before the matched route , in registration order
+        ///
+        /// Returning `MiddlewareResult::Next` continues the chain ,
+        /// `MiddlewareResult::Halt` sends the response as the middleware left it and the route isn't called
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::MiddlewareResult;
+        ///
+        /// let mut app = express::Application::new();
+        /// app.use_middleware(|request, response| {
+        ///     if request.headers.contains_key("authorization") {
+        ///         return MiddlewareResult::Next;
+        ///     }
+        ///     *response = std::mem::take(response).error(401, "Unauthorized");
+        ///     MiddlewareResult::Halt
+        /// });
+        /// app.get("/".to_string(), |request, response| response.status(200));
+        /// ```
+        pub fn use_middleware<F>(&mut self, function: F)
+        where
+            F: Fn(&mut Request, &mut Response) -> MiddlewareResult + Send + Sync + 'static,
+        {
+            self.middleware.push(Box::new(function));
+        }
+
+        /// Register a middleware for the requests under `prefix` , it runs after the global middleware
+        ///
+        /// It runs for every path under the prefix (`/admin` and `/admin/users` for `/admin` , not `/administrator`) ,
+        /// including paths no route matches so they can't be probed without passing it
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::MiddlewareResult;
+        /// use std::io::{Read, Write};
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.quiet(true);
+        ///     app.group_middleware("/admin", |request, response| {
+        ///         if request.headers.contains_key("Authorization") {
+        ///             return MiddlewareResult::Next;
+        ///         }
+        ///         *response = std::mem::take(response).error(401, "Unauthorized");
+        ///         MiddlewareResult::Halt
+        ///     });
+        ///     app.get("/admin/*page".to_string(), |request, response| response.status(200));
+        ///     app.get("/public/*page".to_string(), |request, response| response.status(200));
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        ///
+        /// let status = |request: &str| {
+        ///     let mut stream = std::net::TcpStream::connect(address).unwrap();
+        ///     stream.write_all(format!("{}Connection: close\r\n\r\n", request).as_bytes()).unwrap();
+        ///     let mut answer = String::new();
+        ///     stream.read_to_string(&mut answer).unwrap();
+        ///     answer.lines().next().unwrap().to_string()
+        /// };
+        /// assert_eq!(status("GET /admin/users HTTP/1.1\r\n"), "HTTP/1.1 401 Unauthorized");
+        /// assert_eq!(status("GET /admin/users HTTP/1.1\r\nAuthorization: Bearer t\r\n"), "HTTP/1.1 200 OK");
+        /// assert_eq!(status("GET /public/about HTTP/1.1\r\n"), "HTTP/1.1 200 OK");
+        /// ```
+        pub fn group_middleware<F>(&mut self, prefix: &str, function: F)
+        where
+            F: Fn(&mut Request, &mut Response) -> MiddlewareResult + Send + Sync + 'static,
+        {
+            let prefix = prefix.trim_end_matches('/').to_string();
+            self.group_middleware.push((prefix, Box::new(function)));
+        }
+
+        /// Remove a base path from every incoming request before matching routes
+        ///
+        /// Useful when the server is deployed behind a reverse proxy under a sub path ,
+        /// requests that don't start with the prefix get a 404
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.strip_prefix("/myapp");
+        /// // "/myapp/users" is handled by this route
+        /// app.get("/users".to_string(), |request, response| response.status(200));
+        /// ```
+        pub fn strip_prefix(&mut self, prefix: &str) {
+            let prefix = prefix.trim_end_matches('/');
+            if prefix.is_empty() {
+                self.strip_prefix = None;
+            } else {
+                self.strip_prefix = Some(prefix.to_string());
+            }
+        }
+
+        // start the server , takes a port as argument
+        pub fn listen(&mut self, port: i32) {
+            self.listen_on(&format!("127.0.0.1:{}", port)).unwrap();
+        }
+
+        /// Start the server on a full socket address , to be reachable from other machines or inside a container
+        ///
+        /// IPv6 literals are written in brackets , `[::1]:8080` \
+        /// It returns the error when the address can't be bound. **Blocking call.**
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.listen_on("0.0.0.0:8080").unwrap();
+        /// ```
+        pub fn listen_on(&mut self, address: &str) -> std::io::Result<()> {
+            let listener = self.bind(address)?;
+            self.serve(listener);
+            return Ok(());
+        }
+
+        /// Start the server like [Application::listen] , but send the bound address through `ready`
+        /// once the listener is accepting connections
+        ///
+        /// This is useful when the server runs on a background thread (tests for example) ,
+        /// passing port 0 lets the OS pick a free port which is then reported through `ready` \
+        /// It returns the error when the port can't be bound , `ready` is dropped without a message then
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// use std::sync::mpsc;
+        ///
+        /// let (ready, started) = mpsc::channel();
+        /// std::thread::spawn(move || {
+        ///     let mut app = express::Application::new();
+        ///     app.listen_with_ready(0, ready).unwrap();
+        /// });
+        /// let address = started.recv().unwrap();
+        /// let stream = std::net::TcpStream::connect(address).unwrap();
+        /// ```
+        pub fn listen_with_ready(
+            &mut self,
+            port: i32,
+            ready: Sender<SocketAddr>,
+        ) -> std::io::Result<()> {
+            let listener = self.bind(&format!("127.0.0.1:{}", port))?;
+            let _ = ready.send(listener.local_addr()?);
+            self.serve(listener);
+            return Ok(());
+        }
+
+        // bind a listener on `address` and log the address it got
+        fn bind(&self, address: &str) -> std::io::Result<TcpListener> {
+            let listener = TcpListener::bind(address)?;
+            self.log(&format!("Started server on {}", listener.local_addr()?));
+            return Ok(listener);
+        }
+
+        /// Run the server on a listener that is already bound
+        ///
+        /// Useful for socket activation or to bind port 0 and read the chosen port first. **Blocking call.**
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        /// println!("listening on {}", listener.local_addr().unwrap());
+        ///
+        /// let mut app = express::Application::new();
+        /// app.serve(listener);
+        /// ```
+        pub fn serve(&mut self, listener: TcpListener) {
+            self.shutdown.listening_on(listener.local_addr().unwrap());
+
+            let mut incoming = listener.incoming();
+            self.run(|| {
+                loop {
+                    // a shutdown requested before the address was known couldn't wake the accept up
+                    if self.shutdown.is_requested() {
+                        return None;
+                    }
+                    let stream = incoming.next()?;
+                    if self.shutdown.is_requested() {
+                        return None;
+                    }
+                    // a failed accept only concerns that connection
+                    if let Ok(socket) = stream {
+                        return Some(socket);
+                    }
+                }
+            });
+        }
+
+        /// Start the server on several addresses at once (IPv4 and IPv6 , or several interfaces)
+        ///
+        /// Every address is bound before serving , the first one that fails is reported in the error ,
+        /// an empty list is an `InvalidInput` error. **Blocking call.**
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.listen_many(&["127.0.0.1:8080", "[::1]:8080"]).unwrap();
+        /// ```
+        pub fn listen_many(&mut self, addresses: &[&str]) -> std::io::Result<()> {
+            if addresses.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "no address to listen on",
+                ));
+            }
+            let mut listeners = Vec::new();
+            for address in addresses {
+                let listener = self.bind(address).map_err(|error| {
+                    std::io::Error::new(
+                        error.kind(),
+                        format!("failed to bind {}: {}", address, error),
+                    )
+                })?;
+                self.shutdown.listening_on(listener.local_addr()?);
+                listeners.push(listener);
+            }
+
+            // every listener gets a thread blocked on its accept , they feed a single queue of connections
+            // and stop when a shutdown is requested (which connects to each of them to wake them up)
+            let (accepted, connections) = mpsc::channel::<TcpStream>();
+            thread::scope(|scope| {
+                for listener in &listeners {
+                    let accepted = accepted.clone();
+                    let shutdown = &self.shutdown;
+                    scope.spawn(move || {
+                        while !shutdown.is_requested() {
+                            let stream = listener.accept();
+                            if shutdown.is_requested() {
+                                break;
+                            }
+                            // a failed accept only concerns that connection
+                            if let Ok((socket, _)) = stream
+                                && accepted.send(socket).is_err()
+                            {
+                                break;
+                            }
+                        }
+                    });
+                }
+                // the queue ends once every accept thread stopped
+                drop(accepted);
+                self.run(|| {
+                    let socket = connections.recv().ok()?;
+                    if self.shutdown.is_requested() {
+                        return None;
+                    }
+                    return Some(socket);
+                });
+            });
+            return Ok(());
+        }
+
+        /// Start the server like [Application::listen] , with a fixed pool of `workers` threads serving the connections
+        ///
+        /// By default every connection gets its own thread , a pool bounds the number of threads ,
+        /// connections wait for a free worker. **Blocking call.**
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// # use express_rs::express;
+        /// let mut app = express::Application::new();
+        /// app.listen_with_workers(8080, 8);
+        /// ```
+        pub fn listen_with_workers(&mut self, port: i32, workers: usize) {
+            self.workers = Some(workers.max(1));
+            self.listen(port);
+        }
+
+        // serve the connections returned by `accept` until it returns None , each on its own thread
+        // or on the worker pool , then wait for the connections still being served
+        fn run<A>(&self, mut accept: A)
+        where
+            A: FnMut() -> Option<TcpStream>,
+        {
+            let (jobs, receiver) = mpsc::channel::<TcpStream>();
+            let receiver = Mutex::new(receiver);
+            thread::scope(|scope| {
+                for _ in 0..self.workers.unwrap_or(0) {
+                    let receiver = &receiver;
+                    scope.spawn(move || {
+                        loop {
+                            let job = receiver.lock().unwrap().recv();
+                            match job {
+                                Ok(socket) => self.serve_isolated(socket),
+                                Err(_) => break,
+                            }
+                        }
+                    });
+                }
+
+                while let Some(socket) = accept() {
+                    if self.workers.is_some() {
+                        let _ = jobs.send(socket);
+                    } else {
+                        scope.spawn(move || self.serve_isolated(socket));
+                    }
+                }
+                drop(jobs);
+            });
+        }
+
+        // take a connection slot for the client of `socket` , following the overload policy ,
+        // called by the thread serving the connection so waiting for a slot doesn't hold back the accept loop
+        fn admit(&self, socket: &TcpStream) -> ConnectionAdmission {
+            let peer = socket.peer_addr().ok().map(|address| address.ip());
+            match (self.max_connections_per_ip, peer) {
+                (Some(limit), Some(ip)) if self.overload_policy == OverloadPolicy::Queue => {
+                    Ok(Some(self.connections_per_ip.acquire_waiting(ip, limit)))
+                }
+                (Some(limit), Some(ip)) => match self.connections_per_ip.acquire(ip, limit) {
+                    Some(slot) => Ok(Some(slot)),
+                    None => {
+                        let message = format!("More than {} connections from {}", limit, ip);
+                        Err(ErrorResponse::new(503, message))
+                    }
+                },
+                _ => Ok(None),
+            }
+        }
+
+        // a panic while serving a connection only ends that connection , never the server
+        fn serve_isolated(&self, socket: TcpStream) {
+            let _ = catch_panic(self.quiet, || self.serve_connection(socket));
+        }
+
+        // serve every request of an accepted connection , then close it
+        fn serve_connection(&self, socket: TcpStream) {
+            let admission = self.admit(&socket);
+            let mut stream = match self.open_stream(socket) {
+                Some(stream) => stream,
+                None => return,
+            };
+
+            // held until the connection is closed
+            let _connection_slot = match admission {
+                Ok(slot) => slot,
+                Err(error) => {
+                    error.reject(self.json_errors, &mut stream);
+                    stream.close();
+                    return;
+                }
+            };
+            let mut buffer = Vec::new();
+
+            for index in 0..self.keep_alive_max_requests {
+                // the first request is waited for like the next ones , so a client can't hold a connection
+                // (and its slot) without sending anything
+                if buffer.is_empty() && !self.await_next_request(&mut stream, &mut buffer) {
+                    break;
+                }
+                let mut request = match Request::new(
+                    &mut stream,
+                    &mut buffer,
+                    self.body_limits(),
+                    self.request_timeout,
+                ) {
+                    Ok(request) => request,
+                    // the client closed the connection between two requests
+                    Err(RequestError::IncompleteHeaders) if buffer.is_empty() => break,
+                    Err(error) => {
+                        if let Some(error) = error.error_response() {
+                            error.reject(self.json_errors, &mut stream);
+                        }
+                        break;
+                    }
+                };
+                request.connection_index = index;
+                request.trust_proxy = self.trust_proxy;
+                request.secure = stream.is_secure();
+                if let Some(name) = &self.request_id_header {
+                    request.request_id = Some(request_id(&request, name));
+                }
+
+                if self.strict_headers && !request.valid_header {
+                    ErrorResponse::new(400, "Header fields are not valid UTF-8")
+                        .reject(self.json_errors, &mut stream);
+                    break;
+                }
+
+                if let Some(before_route) = &self.before_route {
+                    before_route(&mut request);
+                }
+
+                let response = self.new_response(&request);
+                self.execute_route(
+                    request.route.to_string(),
+                    request.method.clone(),
+                    &mut request,
+                    response,
+                    &mut stream,
+                );
+
+                if !self.keeps_connection(&request) {
+                    break;
+                }
+            }
+            stream.close();
+        }
+
+        // wait on a connection for the first bytes of its next request , at most for the
+        // idle limit , false when the client closed the connection or stayed idle too long
+        fn await_next_request(&self, stream: &mut Stream, buffer: &mut Vec<u8>) -> bool {
+            let idle = self.keep_alive_idle.unwrap_or(self.keep_alive_timeout);
+            if stream.set_read_timeout(Some(idle)).is_err() {
+                return false;
+            }
+            let mut temp = [0u8; 4096];
+            let read = loop {
+                match stream.read(&mut temp) {
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                    read => break read,
+                }
+            };
+            if stream.set_read_timeout(None).is_err() {
+                return false;
+            }
+            match read {
+                Ok(n) if n > 0 => {
+                    buffer.extend_from_slice(&temp[..n]);
+                    return true;
+                }
+                _ => return false,
+            }
+        }
+
+        // wrap an accepted socket , completing the TLS handshake when TLS is configured ,
+        // connections failing the handshake are dropped
+        #[cfg(feature = "tls")]
+        fn open_stream(&self, mut socket: TcpStream) -> Option<Stream> {
+            let config = match &self.tls {
+                Some(config) => config.clone(),
+                None => return Some(Stream::Plain(socket)),
+            };
+            let mut connection = rustls::ServerConnection::new(config).ok()?;
+            while connection.is_handshaking() {
+                connection.complete_io(&mut socket).ok()?;
+            }
+            return Some(Stream::Tls(Box::new(rustls::StreamOwned::new(
+                connection, socket,
+            ))));
+        }
+
+        #[cfg(not(feature = "tls"))]
+        fn open_stream(&self, socket: TcpStream) -> Option<Stream> {
+            return Some(Stream::Plain(socket));
+        }
+
+        fn execute_route(
+            &self,
+            route: String,
+            method: Method,
+            request: &mut Request,
+            response: Response,
+            stream: &mut Stream,
+        ) {
+            let mut filtered_route = route;
+            if filtered_route.contains('?') {
+                let (route, query) = filtered_route.split_once('?').unwrap();
+                match parse_query(query, self.max_params) {
+                    Some(search_params) => request.search_params = Some(search_params),
+                    None => {
+                        self.finalize(
+                            request,
+                            self.too_many_params().into_response(self.json_errors),
+                        )
+                        .send(stream);
+                        return;
+                    }
+                }
+                filtered_route = route.to_string();
+            }
+            if let Some(prefix) = &self.strip_prefix {
+                match strip_path_prefix(&filtered_route, prefix) {
+                    Some(stripped) => filtered_route = stripped,
+                    None => {
+                        let response = self.not_found_response(request, response);
+                        self.finalize(request, response).send(stream);
+                        return;
+                    }
+                }
+            }
+            if filtered_route.starts_with("/") {
+                // preflights are answered before the middleware , they carry no credentials
+                if method == Method::OPTIONS
+                    && let Some(cors) = &self.cors
+                    && self.match_method(&method, &filtered_route).is_none()
+                {
+                    let response = self.preflight(cors, request, &filtered_route, response);
+                    self.finalize(request, response).send(stream);
+                    return;
+                }
+                let mut response = response;
+                let groups = self
+                    .group_middleware
+                    .iter()
+                    .filter(|(prefix, _)| strip_path_prefix(&filtered_route, prefix).is_some())
+                    .map(|(_, middleware)| middleware.as_ref());
+                let chain = self.middleware.iter().map(|middleware| middleware.as_ref());
+                if self.run_middleware(chain.chain(groups), request, &mut response)
+                    == MiddlewareResult::Halt
+                {
+                    self.finalize(request, response).send(stream);
+                    return;
+                }
+                if let Some(error) = self.missing_body(request, &method, &filtered_route) {
+                    let response = error.into_response(self.json_errors);
+                    self.finalize(request, response).send(stream);
+                    return;
+                }
+                match self.match_route(&method, &filtered_route) {
+                    Some(MatchResult {
+                        route: MatchedRoute::Static(registered),
+                        ..
+                    }) => {
+                        let f = self.static_methods[&(registered, filtered_route)].as_ref();
+                        let response = self.run_handler(f, request, response);
+                        self.finalize(request, response).send(stream);
+                    }
+                    Some(MatchResult {
+                        route: MatchedRoute::Dynamic(index),
+                        params,
+                    }) => {
+                        if params.len() > self.max_params {
+                            self.finalize(
+                                request,
+                                self.too_many_params().into_response(self.json_errors),
+                            )
+                            .send(stream);
+                            return;
+                        }
+                        request.params = Some(params);
+                        let function = self.dynamic_methods[index].2.as_ref();
+                        let response = self.run_handler(function, request, response);
+                        self.finalize(request, response).send(stream);
+                    }
+                    Some(MatchResult {
+                        route: MatchedRoute::Mount(index),
+                        ..
+                    }) => {
+                        let (prefix, function) = &self.mounts[index];
+                        let stripped = strip_path_prefix(&filtered_route, prefix).unwrap();
+                        let query = match request.route.find('?') {
+                            Some(start) => request.route[start..].to_string(),
+                            None => String::new(),
+                        };
+                        request.route = format!("{}{}", stripped, query);
+                        let response = self.run_handler(function.as_ref(), request, response);
+                        self.finalize(request, response).send(stream);
+                    }
+                    None if self.default_index
+                        && method == Method::GET
+                        && filtered_route == "/" =>
+                    {
+                        let page = "<h1>express-rs is running</h1>".to_string();
+                        self.finalize(request, response.status(200).html(page))
+                            .send(stream);
+                    }
+                    None => {
+                        let allowed = self.allowed_methods(&filtered_route);
+                        let response = if allowed.is_empty() {
+                            self.not_found_response(request, response)
+                        } else {
+                            method_not_allowed(response, &allowed)
+                        };
+                        self.finalize(request, response).send(stream);
+                    }
+                }
+            } else {
+                let response = self.not_found_response(request, response);
+                self.finalize(request, response).send(stream);
+            }
+        }
+
+        // the answer to a CORS preflight , the allowed origin is added by `finalize`
+        fn preflight(
+            &self,
+            cors: &CorsConfig,
+            request: &Request,
+            path: &str,
+            response: Response,
+        ) -> Response {
+            let mut response = response.status(204);
+            let methods = if cors.methods.is_empty() {
+                self.allowed_methods(path)
+            } else {
+                cors.methods.clone()
+            };
+            let methods: Vec<&str> = methods.iter().map(|method| method.as_str()).collect();
+            if !methods.is_empty() {
+                response.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+            }
+            let headers = match request.find_header("Access-Control-Request-Headers") {
+                Some(requested) if cors.headers.is_empty() => requested.clone(),
+                _ => cors.headers.join(", "),
+            };
+            if !headers.is_empty() {
+                response.set_header("Access-Control-Allow-Headers", &headers);
+            }
+            if let Some(max_age) = cors.max_age {
+                response.set_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+            }
+            return response;
+        }
+
+        // the value of `Access-Control-Allow-Origin` for `request` , None when its origin isn't allowed
+        fn allowed_origin(cors: &CorsConfig, request: &Request) -> Option<String> {
+            match &cors.origins {
+                AllowedOrigins::Any => Some("*".to_string()),
+                AllowedOrigins::List(origins) => {
+                    let origin = request.find_header("Origin")?;
+                    origins.iter().find(|allowed| *allowed == origin).cloned()
+                }
+            }
+        }
+
+        // the error for a request without a body on a route marked by `with_request_body_required`
+        fn missing_body(
+            &self,
+            request: &Request,
+            method: &Method,
+            path: &str,
+        ) -> Option<ErrorResponse> {
+            let required = self
+                .body_required
+                .iter()
+                .any(|(required, route)| required == method && route_matches(route, path));
+            if !required {
+                return None;
+            }
+            if request.find_header("Content-Length").is_none()
+                && request.find_header("Transfer-Encoding").is_none()
+            {
+                return Some(ErrorResponse::new(411, "Request body is required"));
+            }
+            if request.body().as_ref().is_none_or(|body| body.is_empty()) {
+                return Some(ErrorResponse::new(400, "Request body is required"));
+            }
+            return None;
+        }
+
+        // the answer to a request no route matches , from the `not_found` handler when there is one
+        fn not_found_response(&self, request: &Request, response: Response) -> Response {
+            match &self.not_found {
+                Some(handler) => self.run_handler(handler.as_ref(), request, response.status(404)),
+                None => response.error(404, "Not Found"),
+            }
+        }
+
+        /// Returns true when a route is registered that would handle `method` on the concrete `path`
+        ///
+        /// Dynamic routes are matched against the path , no handler is invoked
+        ///
+        /// # Example:
+        /// ```rust
+        /// # use express_rs::express;
+        /// use express::Method;
+        ///
+        /// let mut app = express::Application::new();
+        /// app.get("/user/:id".to_string(), |request, response| response.status(200));
+        /// assert!(app.route_exists(&Method::GET, "/user/42"));
+        /// assert!(!app.route_exists(&Method::POST, "/user/42"));
+        /// ```
+        pub fn route_exists(&self, method: &Method, path: &str) -> bool {
+            let path = path.split_once('?').map_or(path, |(path, _)| path);
+            return self.match_route(method, path).is_some();
+        }
+
+        // find the route registered for `method` and `path` (without the query string) ,
+        // a HEAD request falls back to the GET route ,
+        // then the handlers mounted with `mount_fn` get the paths no route matched
+        pub(crate) fn match_route(&self, method: &Method, path: &str) -> Option<MatchResult> {
+            let mut result = self.match_method(method, path);
+            if result.is_none() && *method == Method::HEAD {
+                result = self.match_method(&Method::GET, path);
+            }
+            if result.is_some() {
+                return result;
+            }
+            for (index, (prefix, _)) in self.mounts.iter().enumerate() {
+                if strip_path_prefix(path, prefix).is_some() {
+                    return Some(MatchResult {
+                        route: MatchedRoute::Mount(index),
+                        params: HashMap::new(),
+                    });
+                }
+            }
+            return None;
+        }
+
+        // the static or dynamic route registered for exactly `method` and `path` ,
+        // static routes take precedence over dynamic ones which are tried in registration order
+        fn match_method(&self, method: &Method, path: &str) -> Option<MatchResult> {
+            if self
+                .static_methods
+                .contains_key(&(method.clone(), path.to_string()))
+            {
+                return Some(MatchResult {
+                    route: MatchedRoute::Static(method.clone()),
+                    params: HashMap::new(),
+                });
+            }
+            let array: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+            for (index, (method_search, segments, _)) in self.dynamic_methods.iter().enumerate() {
+                if method != method_search {
+                    continue;
+                }
+                if let Some(params) = match_segments(segments, &array) {
+                    return Some(MatchResult {
+                        route: MatchedRoute::Dynamic(index),
+                        params,
+                    });
+                }
+            }
+            return None;
+        }
+
+        // the methods that have a route for `path` , a request with another method gets a 405
+        // listing them and a path no method matches gets a 404
+        fn allowed_methods(&self, path: &str) -> Vec<Method> {
+            let registered = self
+                .static_methods
+                .keys()
+                .map(|(method, _)| method)
+                .chain(self.dynamic_methods.iter().map(|(method, _, _)| method));
+            return collect_allowed_methods(registered, |method| {
+                self.match_route(method, path).is_some()
+            });
+        }
+
+        // invoke a route handler , timing it when server timing is enabled
+        fn run_handler(
+            &self,
+            handler: &RouteFunction,
+            request: &Request,
+            response: Response,
+        ) -> Response {
+            let start = Instant::now();
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| handler.handle(request, response)));
+            let mut response = match result {
+                Ok(response) => response,
+                Err(payload) => {
+                    let message = panic_message(payload.as_ref());
+                    match &self.error_handler {
+                        Some(error_handler) => {
+                            let error = self.new_response(request).status(500);
+                            error_handler(request, &message, error)
+                        }
+                        None => self.new_response(request).status(500),
+                    }
+                }
+            };
+            if !self.server_timing {
+                return response;
+            }
+            let duration = start.elapsed().as_secs_f64() * 1000.0;
+            response.headers.push((
+                "Server-Timing".to_string(),
+                format!("app;dur={:.3}", duration),
+            ));
+            return response;
+        }
+
+        // run the middleware chain , on Halt `response` is sent instead of calling the route ,
+        // a panicking middleware halts with the 500 response
+        fn run_middleware<'a>(
+            &self,
+            chain: impl Iterator<Item = &'a MiddlewareFunction>,
+            request: &mut Request,
+            response: &mut Response,
+        ) -> MiddlewareResult {
+            for middleware in chain {
+                let result =
+                    panic::catch_unwind(AssertUnwindSafe(|| middleware(request, response)));
+                match result {
+                    Ok(MiddlewareResult::Next) => {}
+                    Ok(MiddlewareResult::Halt) => return MiddlewareResult::Halt,
+                    Err(payload) => {
+                        let message = panic_message(payload.as_ref());
+                        let error = self.new_response(request).status(500);
+                        *response = match &self.error_handler {
+                            Some(error_handler) => error_handler(request, &message, error),
+                            None => error,
+                        };
+                        return MiddlewareResult::Halt;
+                    }
+                }
+            }
+            return MiddlewareResult::Next;
+        }
+
+        fn body_limits(&self) -> BodyLimits {
+            return BodyLimits {
+                json: self.json_limit,
+                body: self.max_body_size,
+            };
+        }
+
+        // a response for a handler , carrying the application settings it depends on
+        fn new_response(&self, request: &Request) -> Response {
+            let mut response = Response::new();
+            response.json_errors = self.json_errors;
+            response.secure_cookies = self.secure_cookies && request.is_secure();
+            return response;
+        }
+
+        fn too_many_params(&self) -> ErrorResponse {
+            let message = format!("Request has more than {} params", self.max_params);
+            return ErrorResponse::new(400, message);
+        }
+
+        // apply the application wide settings to a response returned by a handler
+        fn finalize(&self, request: &Request, mut response: Response) -> Response {
+            if self.nosniff && response.content_type.is_some() {
+                response.set_header("X-Content-Type-Options", "nosniff");
+            }
+            response.omit_body = request.method == Method::HEAD;
+            if let (Some(name), Some(id)) = (&self.request_id_header, &request.request_id)
+                && !response.has_header(name)
+            {
+                response.set_header(name, id);
+            }
+            if response.conditional && response.status == 200 {
+                let etag = response.find_header("ETag").map(|etag| etag.as_str());
+                if request.fresh(etag, None) {
+                    response.status = 304;
+                }
+            }
+            #[cfg(feature = "gzip")]
+            if let Some(min_size) = self.gzip_min_size {
+                response.compress(request, min_size);
+            }
+            if let Some(cors) = &self.cors {
+                if let AllowedOrigins::List(_) = cors.origins {
+                    // the header depends on the origin , caches must keep one copy per origin
+                    response.append_to_header("Vary", "Origin");
+                }
+                if let Some(origin) = Self::allowed_origin(cors, request)
+                    && !response.has_header("Access-Control-Allow-Origin")
+                {
+                    response.set_header("Access-Control-Allow-Origin", &origin);
+                }
+            }
+            // chunked encoding is HTTP/1.1 only
+            if request.is_http10() {
+                response.prefer_chunked = false;
+                response.collect_chunks();
+            }
+            let remaining = self.keep_alive_max_requests - (request.connection_index + 1);
+            if !self.keeps_connection(request) {
+                // closing is already the default for HTTP/1.0
+                if !request.is_http10() {
+                    response.set_header("Connection", "close");
+                }
+            } else {
+                // the connection stays open , say so to HTTP/1.1 clients too
+                response.set_header("Connection", "keep-alive");
+                let timeout = self.keep_alive_timeout.as_secs();
+                response.set_header(
+                    "Keep-Alive",
+                    &format!("timeout={}, max={}", timeout, remaining),
+                );
+            }
+            return response;
+        }
+
+        // true when the connection stays open after answering `request`
+        fn keeps_connection(&self, request: &Request) -> bool {
+            let remaining = self.keep_alive_max_requests - (request.connection_index + 1);
+            return self.keep_alive && remaining > 0 && request.is_keep_alive();
+        }
+
+        fn add_new_route(&mut self, path: String, method: Method, function: Box<RouteFunction>) {
+            if let Some(vec) = route_segments(&path) {
+                // a duplicate is replaced in place , so it keeps its priority among the dynamic routes
+                let existing = self
+                    .dynamic_methods
+                    .iter()
+                    .position(|(m, segments, _)| *m == method && same_route_shape(segments, &vec));
+                match existing {
+                    Some(index) => {
+                        self.duplicate_route(&method, &path);
+                        self.dynamic_methods[index] = (method, vec, function);
+                    }
+                    None => self.dynamic_methods.push((method, vec, function)),
+                }
+            } else {
+                if self
+                    .static_methods
+                    .contains_key(&(method.clone(), path.clone()))
+                {
+                    self.duplicate_route(&method, &path);
+                }
+                self.static_methods.insert((method, path), function);
+            }
+        }
+
+        fn duplicate_route(&self, method: &Method, path: &str) {
+            match self.duplicate_policy {
+                DuplicatePolicy::Panic => {
+                    panic!("route {} {} is already registered", method.as_str(), path)
+                }
+                DuplicatePolicy::LastWins => self.log(&format!(
+                    "warning: route {} {} is registered twice, the last handler is used",
+                    method.as_str(),
+                    path
+                )),
+            }
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    type AsyncRouteFunction = dyn Fn(&Request, Response) -> Pin<Box<dyn Future<Output = Response> + Send>>
+        + Send
+        + Sync
+        + 'static;
+
+    /// An application running on tokio , its handlers return a future of the response
+    ///
+    /// Available with the `tokio` feature. A handler gets the request by reference but the future it returns
+    /// can't borrow it , read what the future needs from the request first and move it in \
+    /// Routes are matched like in [Application] (static routes first , then `:name` and `*name` routes in registration order) ,
+    /// a HEAD request falls back to the GET route and unmatched requests get a 404 or a 405 \
+    /// A panicking handler gets a 500 response , and [AsyncApplication::shutdown_handle] stops the server
+    ///
+    /// Only the options defined here are supported , the rest of [Application] is not available yet:
+    /// middleware , CORS , mounted routers and static files , TLS , request IDs , compression ,
+    /// error and not found handlers , connection limits and overload policies , `Server-Timing` and logging
+    ///
+    /// # Example:
+    /// ```rust
+    /// use express_rs::express::AsyncApplication;
+    /// use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    ///
+    /// let mut app = AsyncApplication::new();
+    /// app.get("/user/:id".to_string(), |request, response| {
+    ///     let id = request.get_param("id").unwrap();
+    ///     async move { response.status(200).text(format!("user {}", id)) }
+    /// });
+    ///
+    /// let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    /// runtime.block_on(async {
+    ///     let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    ///     let address = listener.local_addr().unwrap();
+    ///     tokio::spawn(app.serve(listener));
+    ///
+    ///     let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+    ///     stream.write_all(b"GET /user/7 HTTP/1.1\r\nConnection: close\r\n\r\n").await.unwrap();
+    ///     let mut answer = String::new();
+    ///     stream.read_to_string(&mut answer).await.unwrap();
+    ///     assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"));
+    ///     assert!(answer.ends_with("user 7"));
+    /// });
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub struct AsyncApplication {
+        static_methods: HashMap<(Method, String), Box<AsyncRouteFunction>>,
+        dynamic_methods: Vec<(Method, Vec<RouteSegment>, Box<AsyncRouteFunction>)>,
+        keep_alive_timeout: Duration,
+        request_timeout: Duration,
+        keep_alive_max_requests: usize,
+        max_params: usize,
+        json_errors: bool,
+        max_body_size: Option<usize>,
+        shutdown: ShutdownHandle,
+    }
+
+    #[cfg(feature = "tokio")]
+    impl Default for AsyncApplication {
+        fn default() -> Self {
+            return AsyncApplication::new();
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl AsyncApplication {
+        // create a new application , with the defaults of `Application::new`
+        pub fn new() -> AsyncApplication {
+            return AsyncApplication {
+                static_methods: HashMap::new(),
+                dynamic_methods: Vec::new(),
+                keep_alive_timeout: Duration::from_secs(5),
+                request_timeout: Duration::from_secs(30),
+                keep_alive_max_requests: 100,
+                max_params: 1000,
+                json_errors: false,
+                max_body_size: Some(DEFAULT_MAX_BODY_SIZE),
+                shutdown: ShutdownHandle::default(),
+            };
+        }
+
+        pub fn get<F, Fut>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            self.add_new_route(route, Method::GET, function);
+        }
+        pub fn post<F, Fut>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            self.add_new_route(route, Method::POST, function);
+        }
+        pub fn put<F, Fut>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            self.add_new_route(route, Method::PUT, function);
+        }
+        pub fn patch<F, Fut>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            self.add_new_route(route, Method::PATCH, function);
+        }
+        pub fn delete<F, Fut>(&mut self, route: String, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            self.add_new_route(route, Method::DELETE, function);
+        }
+
+        /// Set how long a connection waits for the first bytes of its next request , the first one included (5 seconds by default)
+        pub fn keep_alive_timeout(&mut self, timeout: Duration) {
+            self.keep_alive_timeout = timeout;
+        }
+
+        /// Set how long a request may take to arrive once it started arriving (30 seconds by default) ,
+        /// see [Application::request_timeout]
+        pub fn request_timeout(&mut self, timeout: Duration) {
+            self.request_timeout = timeout;
+        }
+
+        /// Answer errors the server produces (404 , 413 ...) with a JSON body , see [Application::json_errors]
+        pub fn json_errors(&mut self, enabled: bool) {
+            self.json_errors = enabled;
+        }
+
+        /// Limit the size of request bodies (10 MiB by default , `None` for no limit) , see [Application::max_body_size]
+        pub fn max_body_size(&mut self, bytes: Option<usize>) {
+            self.max_body_size = bytes;
+        }
+
+        /// Returns a [ShutdownHandle] that stops this application's accept loop when triggered ,
+        /// `serve` returns once the connections being served are finished
+        pub fn shutdown_handle(&self) -> ShutdownHandle {
+            return self.shutdown.clone();
+        }
+
+        /// Start the server on `127.0.0.1` , it returns the error when the port can't be bound
+        ///
+        /// # Example:
+        /// ```rust,no_run
+        /// use express_rs::express::AsyncApplication;
+        ///
+        /// let app = AsyncApplication::new();
+        /// let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        /// runtime.block_on(app.listen(8080)).unwrap();
+        /// ```
+        pub async fn listen(self, port: i32) -> std::io::Result<()> {
+            let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+            self.serve(listener).await;
+            return Ok(());
+        }
+
+        /// Run the server on a listener that is already bound , every connection is served by its own task
+        pub async fn serve(self, listener: tokio::net::TcpListener) {
+            if let Ok(address) = listener.local_addr() {
+                self.shutdown.listening_on(address);
+            }
+            let app = Arc::new(self);
+            let mut connections = tokio::task::JoinSet::new();
+            // a shutdown requested before the address was known couldn't wake the accept up
+            while !app.shutdown.is_requested() {
+                let accepted = listener.accept().await;
+                if app.shutdown.is_requested() {
+                    break;
+                }
+                // a failed accept only concerns that connection
+                if let Ok((socket, _)) = accepted {
+                    let app = app.clone();
+                    connections.spawn(async move { app.serve_connection(socket).await });
+                }
+                // forget the connections already finished
+                while connections.try_join_next().is_some() {}
+            }
+            drop(listener);
+            while connections.join_next().await.is_some() {}
+        }
+
+        async fn serve_connection(&self, mut socket: tokio::net::TcpStream) {
+            use tokio::io::AsyncWriteExt;
+
+            let limits = BodyLimits {
+                json: None,
+                body: self.max_body_size,
+            };
+            let mut buffer = Vec::new();
+
+            for index in 0..self.keep_alive_max_requests {
+                // the first request is waited for like the next ones , so a client can't hold a connection
+                // without sending anything
+                if buffer.is_empty() && !self.await_next_request(&mut socket, &mut buffer).await {
+                    break;
+                }
+                let read = Request::read_async(&mut socket, &mut buffer, limits);
+                let read = match tokio::time::timeout(self.request_timeout, read).await {
+                    Ok(read) => read,
+                    Err(_) => Err(RequestError::Io(std::io::ErrorKind::TimedOut.into())),
+                };
+                let mut request = match read {
+                    Ok(request) => request,
+                    // the client closed the connection between two requests
+                    Err(RequestError::IncompleteHeaders) if buffer.is_empty() => break,
+                    Err(error) => {
+                        if let Some(error) = error.error_response() {
+                            let mut response = error.into_response(self.json_errors);
+                            response.set_header("Connection", "close");
+                            let _ = socket.write_all(&response.to_bytes()).await;
+                        }
+                        break;
+                    }
+                };
+                request.connection_index = index;
+
+                let keeps_connection =
+                    index + 1 < self.keep_alive_max_requests && request.is_keep_alive();
+                let response = self.respond(&mut request).await;
+                let response = self.finalize(&request, response, keeps_connection);
+                if socket.write_all(&response.to_bytes()).await.is_err() || !keeps_connection {
+                    break;
+                }
+            }
+            let _ = socket.shutdown().await;
+        }
+
+        // `Application::await_next_request` for a tokio socket , waiting at most `keep_alive_timeout`
+        async fn await_next_request(
+            &self,
+            socket: &mut tokio::net::TcpStream,
+            buffer: &mut Vec<u8>,
+        ) -> bool {
+            use tokio::io::AsyncReadExt;
+
+            let mut temp = [0u8; 4096];
+            let read = tokio::time::timeout(self.keep_alive_timeout, socket.read(&mut temp));
+            match read.await {
+                Ok(Ok(n)) if n > 0 => {
+                    buffer.extend_from_slice(&temp[..n]);
+                    return true;
+                }
+                _ => return false,
+            }
+        }
+
+        // run the handler of the route matching `request` , or answer with a 404 or a 405
+        async fn respond(&self, request: &mut Request) -> Response {
+            let mut response = Response::new();
+            response.json_errors = self.json_errors;
+
+            let mut path = request.route.clone();
+            if let Some((route, query)) = request.route.split_once('?') {
+                match parse_query(query, self.max_params) {
+                    Some(search_params) => request.search_params = Some(search_params),
+                    None => {
+                        let message = format!("Request has more than {} params", self.max_params);
+                        return response.error(400, &message);
+                    }
+                }
+                path = route.to_string();
+            }
+
+            if let Some((function, params)) = self.match_route(&request.method, &path) {
+                request.params = params;
+                // the future can't borrow the request , only it is awaited
+                let future = panic::catch_unwind(AssertUnwindSafe(|| function(request, response)));
+                return self.run_handler(future).await;
+            }
+
+            let registered = self
+                .static_methods
+                .keys()
+                .map(|(method, _)| method)
+                .chain(self.dynamic_methods.iter().map(|(method, _, _)| method));
+            let allowed = collect_allowed_methods(registered, |method| {
+                self.match_route(method, &path).is_some()
+            });
+            if allowed.is_empty() {
+                return response.error(404, "Not Found");
+            }
+            return method_not_allowed(response, &allowed);
+        }
+
+        // await a route handler on its own task , a handler panicking (before or while awaiting) gets a 500
+        async fn run_handler(
+            &self,
+            future: std::thread::Result<Pin<Box<dyn Future<Output = Response> + Send>>>,
+        ) -> Response {
+            let failed = match future {
+                Ok(future) => match tokio::spawn(future).await {
+                    Ok(response) => return response,
+                    Err(error) => error.is_panic(),
+                },
+                Err(_) => true,
+            };
+            let mut error = Response::new();
+            error.json_errors = self.json_errors;
+            if !failed {
+                // the runtime is shutting down and cancelled the task
+                return error.error(503, "Service Unavailable");
+            }
+            return error.status(500);
+        }
+
+        // the handler for `method` and `path` , a HEAD request falls back to the GET route
+        fn match_route(
+            &self,
+            method: &Method,
+            path: &str,
+        ) -> Option<(&AsyncRouteFunction, Option<HashMap<String, String>>)> {
+            let matched = self.match_method(method, path);
+            if matched.is_none() && *method == Method::HEAD {
+                return self.match_method(&Method::GET, path);
+            }
+            return matched;
+        }
+
+        // the handler registered for exactly `method` and `path` and the params of a dynamic route
+        fn match_method(
+            &self,
+            method: &Method,
+            path: &str,
+        ) -> Option<(&AsyncRouteFunction, Option<HashMap<String, String>>)> {
+            if let Some(function) = self.static_methods.get(&(method.clone(), path.to_string())) {
+                return Some((function.as_ref(), None));
+            }
+            let parts: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+            for (registered, segments, function) in &self.dynamic_methods {
+                if registered != method {
+                    continue;
+                }
+                if let Some(params) = match_segments(segments, &parts) {
+                    return Some((function.as_ref(), Some(params)));
+                }
+            }
+            return None;
+        }
+
+        // the connection headers and the HEAD and HTTP/1.0 handling of `Application::finalize`
+        fn finalize(
+            &self,
+            request: &Request,
+            mut response: Response,
+            keeps_connection: bool,
+        ) -> Response {
+            response.omit_body = request.method == Method::HEAD;
+            // chunked encoding is HTTP/1.1 only
+            if request.is_http10() {
+                response.prefer_chunked = false;
+                response.collect_chunks();
+            }
+            if !keeps_connection {
+                // closing is already the default for HTTP/1.0
+                if !request.is_http10() {
+                    response.set_header("Connection", "close");
+                }
+            } else {
+                response.set_header("Connection", "keep-alive");
+            }
+            return response;
+        }
+
+        fn add_new_route<F, Fut>(&mut self, path: String, method: Method, function: F)
+        where
+            F: Fn(&Request, Response) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = Response> + Send + 'static,
+        {
+            let function: Box<AsyncRouteFunction> =
+                Box::new(move |request, response| Box::pin(function(request, response)));
+            match route_segments(&path) {
+                Some(segments) => {
+                    // a route registered again replaces the old one , keeping its priority
+                    let existing = self.dynamic_methods.iter().position(|(m, registered, _)| {
+                        *m == method && same_route_shape(registered, &segments)
+                    });
+                    match existing {
+                        Some(index) => self.dynamic_methods[index] = (method, segments, function),
+                        None => self.dynamic_methods.push((method, segments, function)),
+                    }
+                }
+                None => {
+                    self.static_methods.insert((method, path), function);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn response_text(response: Response) -> String {
+            return String::from_utf8(response.to_bytes()).unwrap();
+        }
+
+        // run an app on a free port of a background thread , `configure` adds its routes and options
+        fn spawn(configure: impl FnOnce(&mut Application) + Send + 'static) -> SocketAddr {
+            let (ready, started) = mpsc::channel();
+            thread::spawn(move || {
+                let mut app = Application::new();
+                app.quiet(true);
+                configure(&mut app);
+                app.listen_with_ready(0, ready).unwrap();
+            });
+            return started.recv().unwrap();
+        }
+
+        // run an async app on a free port of a background thread with its own runtime
+        #[cfg(feature = "tokio")]
+        fn spawn_async(app: AsyncApplication) -> (SocketAddr, thread::JoinHandle<()>) {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let server = thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async {
+                    let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                    app.serve(listener).await;
+                });
+            });
+            return (address, server);
+        }
+
+        // send raw bytes and read everything the server answers until it closes the connection
+        fn exchange(address: SocketAddr, raw: &[u8]) -> String {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            stream.write_all(raw).unwrap();
+            let mut answer = Vec::new();
+            if let Err(error) = stream.read_to_end(&mut answer) {
+                let timed_out = matches!(
+                    error.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                );
+                assert!(!timed_out, "the server kept the connection open");
+            }
+            return String::from_utf8_lossy(&answer).into_owned();
+        }
+
+        #[test]
+        fn header_values_cannot_inject_headers() {
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .text("hi".to_string())
+                    .header("X-Evil", "a\r\nSet-Cookie: pwn=1")
+                    .header("Bad\r\nName", "1")
+                    .header("Content-Type", "text/plain\r\nX-Other: 2")
+                    .with_headers(vec![(
+                        "X-Forwarded".to_string(),
+                        "b\nLocation: /x".to_string(),
+                    )])
+                    .insert_header_if_absent("X-Default", "c\rd"),
+            );
+            assert!(text.contains("X-Evil: aSet-Cookie: pwn=1\r\n"));
+            assert!(text.contains("Content-Type: text/plainX-Other: 2\r\n"));
+            assert!(text.contains("X-Forwarded: bLocation: /x\r\n"));
+            assert!(text.contains("X-Default: cd\r\n"));
+            assert!(!text.contains("Name"));
+            for line in text.split("\r\n") {
+                assert!(!line.starts_with("Set-Cookie") && !line.starts_with("Location"));
+                assert!(!line.contains('\r') && !line.contains('\n'));
+            }
+        }
+
+        #[test]
+        fn insert_header_if_absent_keeps_content_headers_single() {
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .text("hello".to_string())
+                    .insert_header_if_absent("Content-Length", "99")
+                    .insert_header_if_absent("content-type", "application/json"),
+            );
+            assert_eq!(
+                text,
+                "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nhello"
+            );
+
+            let text = response_text(
+                Response::default()
+                    .status(204)
+                    .insert_header_if_absent("Content-Type", "text/html")
+                    .insert_header_if_absent("Content-Length", "7"),
+            );
+            assert_eq!(text, "HTTP/1.1 204 No Content\r\n\r\n");
+        }
+
+        #[test]
+        fn parse_query_counts_every_pair() {
+            assert_eq!(parse_query("a=1&a=2&b", 3).unwrap().len(), 1);
+            assert!(parse_query("a=1&a=2&a=3", 2).is_none());
+            assert!(parse_query("a&b&c", 2).is_none());
+            assert_eq!(parse_query("a=1&&b=2&", 2).unwrap().len(), 2);
+        }
+
+        #[test]
+        fn too_many_query_params_is_400() {
+            let address = spawn(|app| {
+                app.max_params(10);
+                app.get("/search".to_string(), |_, response| response.status(200));
+            });
+            let query = vec!["k"; 3000].join("&");
+            let raw = format!(
+                "GET /search?{} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                query
+            );
+            let answer = exchange(address, raw.as_bytes());
+            assert!(answer.starts_with("HTTP/1.1 400 "), "{}", answer);
+            assert!(answer.contains("Request has more than 10 params"));
+
+            let raw = b"GET /search?a=1&b HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            assert!(exchange(address, raw).starts_with("HTTP/1.1 200 "));
+        }
+
+        // one client holds the only connection slot without sending a request , the first-request
+        // timeout closes it so the slot is given back
+        fn overloaded(policy: OverloadPolicy) -> String {
+            let address = spawn(move |app| {
+                app.max_connections_per_ip(1);
+                app.overload_policy(policy);
+                app.max_keepalive_idle(Duration::from_millis(300));
+                app.get("/".to_string(), |_, response| response.status(200));
+            });
+            let idle = TcpStream::connect(address).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            let started = Instant::now();
+            let answer = exchange(
+                address,
+                b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(started.elapsed() < Duration::from_secs(4));
+            drop(idle);
+            return answer;
+        }
+
+        #[test]
+        fn overload_policies() {
+            assert!(overloaded(OverloadPolicy::Reject).starts_with("HTTP/1.1 503 "));
+            assert!(overloaded(OverloadPolicy::Queue).starts_with("HTTP/1.1 200 "));
+        }
+
+        #[test]
+        fn disabled_keep_alive_closes_after_one_response() {
+            let address = spawn(|app| {
+                app.enable_keep_alive(false);
+                app.get("/".to_string(), |_, response| {
+                    response.status(200).text("hi".to_string())
+                });
+            });
+            let request = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: keep-alive\r\n\r\n";
+            let answer = exchange(address, &[&request[..], &request[..]].concat());
+            assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 1);
+            assert!(answer.contains("Connection: close\r\n"));
+            assert!(!answer.contains("keep-alive"));
+        }
+
+        #[test]
+        fn match_route_table() {
+            let mut app = Application::new();
+            app.get("/users/:id".to_string(), |_, response| response);
+            app.get("/users/new".to_string(), |_, response| response);
+            app.get("/users/:id/posts/:post".to_string(), |_, response| response);
+            app.post("/users".to_string(), |_, response| response);
+            app.get("/files/*path".to_string(), |_, response| response);
+            app.mount_fn("/admin", |_, response| response);
+
+            let table = [
+                (
+                    Method::GET,
+                    "/users/new",
+                    Some(MatchedRoute::Static(Method::GET)),
+                    vec![],
+                ),
+                (
+                    Method::GET,
+                    "/users/42",
+                    Some(MatchedRoute::Dynamic(0)),
+                    vec![("id", "42")],
+                ),
+                (
+                    Method::GET,
+                    "/users/a%20b",
+                    Some(MatchedRoute::Dynamic(0)),
+                    vec![("id", "a b")],
+                ),
+                (
+                    Method::GET,
+                    "/users/7/posts/9",
+                    Some(MatchedRoute::Dynamic(1)),
+                    vec![("id", "7"), ("post", "9")],
+                ),
+                (
+                    Method::HEAD,
+                    "/users/new",
+                    Some(MatchedRoute::Static(Method::GET)),
+                    vec![],
+                ),
+                (
+                    Method::POST,
+                    "/users",
+                    Some(MatchedRoute::Static(Method::POST)),
+                    vec![],
+                ),
+                (
+                    Method::GET,
+                    "/files/a/b.txt",
+                    Some(MatchedRoute::Dynamic(2)),
+                    vec![("path", "a/b.txt")],
+                ),
+                (
+                    Method::GET,
+                    "/admin/dashboard",
+                    Some(MatchedRoute::Mount(0)),
+                    vec![],
+                ),
+                (Method::POST, "/users/42", None, vec![]),
+                (Method::GET, "/users", None, vec![]),
+                (Method::GET, "/users/7/posts", None, vec![]),
+                (Method::GET, "/nothing", None, vec![]),
+            ];
+            for (method, path, route, params) in table {
+                let result = app.match_route(&method, path);
+                assert_eq!(
+                    result.as_ref().map(|result| &result.route),
+                    route.as_ref(),
+                    "{}",
+                    path
+                );
+                let params: HashMap<String, String> = params
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect();
+                assert_eq!(
+                    result.map(|result| result.params).unwrap_or_default(),
+                    params,
+                    "{}",
+                    path
+                );
+            }
+        }
+
+        // counts the calls reaching the socket
+        #[derive(Default)]
+        struct CountingWriter {
+            written: Vec<u8>,
+            writes: usize,
+            flushes: usize,
+        }
+
+        impl Write for CountingWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.writes += 1;
+                self.written.extend_from_slice(buf);
+                return Ok(buf.len());
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.flushes += 1;
+                return Ok(());
+            }
+        }
+
+        #[test]
+        fn send_coalesces_writes_and_flushes() {
+            let response = || {
+                Response::default()
+                    .status(200)
+                    .header("X-One", "1")
+                    .header("X-Two", "2")
+                    .vary("Accept")
+                    .text("hello".to_string())
+            };
+            let mut writer = CountingWriter::default();
+            response().send(&mut writer);
+            assert_eq!(writer.writes, 1);
+            assert_eq!(writer.flushes, 1);
+            assert_eq!(writer.written, response().to_bytes());
+        }
+
+        #[test]
+        fn panics_become_500_and_the_server_keeps_serving() {
+            let address = spawn(|app| {
+                app.use_middleware(|request, _| {
+                    if request.route == "/middleware" {
+                        panic!("middleware failed");
+                    }
+                    return MiddlewareResult::Next;
+                });
+                app.get("/handler".to_string(), |_, _| panic!("handler failed"));
+                app.get("/middleware".to_string(), |_, response| {
+                    response.status(200)
+                });
+                app.get("/ok".to_string(), |_, response| response.status(200));
+            });
+            let raw = b"GET /handler HTTP/1.1\r\nHost: x\r\n\r\n\
+                GET /middleware HTTP/1.1\r\nHost: x\r\n\r\n\
+                GET /ok HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            let statuses: Vec<&str> = answer
+                .split("HTTP/1.1 ")
+                .skip(1)
+                .map(|response| &response[..3])
+                .collect();
+            assert_eq!(statuses, ["500", "500", "200"]);
+            let raw = b"GET /ok HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            assert!(exchange(address, raw).starts_with("HTTP/1.1 200 "));
+        }
+
+        #[cfg(feature = "signals")]
+        #[test]
+        fn signals_stop_the_registered_applications() {
+            let (ready, started) = mpsc::channel();
+            let server = thread::spawn(move || {
+                let mut app = Application::new();
+                app.quiet(true);
+                app.graceful_on_signals().unwrap();
+                app.listen_with_ready(0, ready).unwrap();
+            });
+            let address = started.recv().unwrap();
+            // what the handler installed for SIGINT / SIGTERM runs
+            shutdown_signalled();
+            server.join().unwrap();
+            assert!(TcpStream::connect(address).is_err());
+        }
+
+        #[cfg(feature = "tls")]
+        #[test]
+        fn tls_handshake_and_request() {
+            let certified =
+                rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            let cert = certified.cert.pem();
+            let key = certified.key_pair.serialize_pem();
+            let address = spawn(move |app| {
+                app.with_tls_from_pem_bytes(cert.as_bytes(), key.as_bytes())
+                    .unwrap();
+                app.get("/secure".to_string(), |request, response| {
+                    response
+                        .status(200)
+                        .text(format!("secure: {}", request.is_secure()))
+                });
+            });
+
+            let mut roots = rustls::RootCertStore::empty();
+            roots.add(certified.cert.der().clone()).unwrap();
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = rustls::ClientConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let name = rustls::pki_types::ServerName::try_from("localhost").unwrap();
+            let connection = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+            let mut stream =
+                rustls::StreamOwned::new(connection, TcpStream::connect(address).unwrap());
+            stream
+                .write_all(b"GET /secure HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut answer = String::new();
+            let _ = stream.read_to_string(&mut answer);
+            assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"), "{}", answer);
+            assert!(answer.ends_with("\r\n\r\nsecure: true"));
+        }
+
+        #[test]
+        fn is_valid_json_table() {
+            let valid = [
+                "null",
+                " true ",
+                r#"{"a": [1, 2, {"b": null}], "c": {}}"#,
+                r#""quote \" slash \/ tab \t unicode \u00e9 \uD83D\uDE00""#,
+                "0",
+                "-0.5",
+                "1e10",
+                "-12.5E-3",
+                "[[[[[]]]]]",
+            ];
+            for text in valid {
+                assert!(is_valid_json(text), "{}", text);
+            }
+            let invalid = [
+                "",
+                "{",
+                r#"{"a" 1}"#,
+                r#"{"a": 1,}"#,
+                "[1, 2",
+                "[1 2]",
+                r#""bad escape \x""#,
+                r#""short \u12""#,
+                "\"control \n char\"",
+                "01",
+                "1.",
+                "-",
+                "1e",
+                "+1",
+                "{} {}",
+                "null x",
+                "tru",
+            ];
+            for text in invalid {
+                assert!(!is_valid_json(text), "{}", text);
+            }
+            let deep = format!("{}{}", "[".repeat(300), "]".repeat(300));
+            assert!(!is_valid_json(&deep));
+        }
+
+        #[cfg(debug_assertions)]
+        #[test]
+        #[should_panic(expected = "invalid JSON response body")]
+        fn json_flags_invalid_bodies_in_debug() {
+            let _ = Response::default().json("{\"a\": }");
+        }
+
+        #[test]
+        fn pipelined_requests_are_answered_in_order() {
+            let address = spawn(|app| {
+                app.get("/first".to_string(), |_, response| {
+                    response.status(200).text("one".to_string())
+                });
+                app.get("/second".to_string(), |_, response| {
+                    response.status(200).text("two".to_string())
+                });
+            });
+            let raw = b"GET /first HTTP/1.1\r\nHost: x\r\n\r\nGET /second HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            assert_eq!(answer.matches("HTTP/1.1 200 OK").count(), 2);
+            let first = answer.find("\r\n\r\none").unwrap();
+            let second = answer.find("\r\n\r\ntwo").unwrap();
+            assert!(first < second);
+            assert!(answer.ends_with("two"));
+        }
+
+        #[test]
+        fn method_not_allowed_or_not_found() {
+            let address = spawn(|app| {
+                app.get("/items".to_string(), |_, response| response.status(200));
+                app.post("/items".to_string(), |_, response| response.status(201));
+                app.delete("/items/:id".to_string(), |_, response| response.status(204));
+            });
+            let table = [
+                ("PUT", "/items", "405", Some("GET, HEAD, POST")),
+                ("DELETE", "/items", "405", Some("GET, HEAD, POST")),
+                ("GET", "/items/3", "405", Some("DELETE")),
+                ("POST", "/items/3", "405", Some("DELETE")),
+                ("GET", "/missing", "404", None),
+                ("DELETE", "/items/3/extra", "404", None),
+                ("HEAD", "/items", "200", None),
+                ("DELETE", "/items/3", "204", None),
+            ];
+            for (method, path, status, allow) in table {
+                let raw = format!(
+                    "{} {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    method, path
+                );
+                let answer = exchange(address, raw.as_bytes());
+                assert!(
+                    answer.starts_with(&format!("HTTP/1.1 {} ", status)),
+                    "{} {}: {}",
+                    method,
+                    path,
+                    answer
+                );
+                match allow {
+                    Some(allow) => assert!(
+                        answer.contains(&format!("\r\nAllow: {}\r\n", allow)),
+                        "{}",
+                        answer
+                    ),
+                    None => assert!(!answer.contains("\r\nAllow:"), "{}", answer),
+                }
+            }
+        }
+
+        #[test]
+        fn parse_rejects_malformed_requests() {
+            let huge_header = format!(
+                "GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n",
+                "a".repeat(MAX_HEADER_BYTES)
+            );
+            let long_uri = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_URI_LENGTH));
+            let table: [(&[u8], ParseError); 11] = [
+                (b"garbage\r\n\r\n", ParseError::MalformedStartLine),
+                (b"GET /\r\n\r\n", ParseError::MalformedStartLine),
+                (b"G(T / HTTP/1.1\r\n\r\n", ParseError::UnknownMethod),
+                (
+                    b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+                    ParseError::InvalidContentLength,
+                ),
+                (
+                    b"POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n",
+                    ParseError::InvalidContentLength,
+                ),
+                (
+                    b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+                    ParseError::InvalidContentLength,
+                ),
+                (
+                    b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+                    ParseError::UnsupportedTransferEncoding,
+                ),
+                (
+                    b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab",
+                    ParseError::Incomplete,
+                ),
+                (
+                    b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n",
+                    ParseError::Http2Preface,
+                ),
+                (huge_header.as_bytes(), ParseError::HeadersTooLarge),
+                (long_uri.as_bytes(), ParseError::UriTooLong),
+            ];
+            for (raw, error) in table {
+                assert_eq!(
+                    Request::parse(raw).err(),
+                    Some(error),
+                    "{}",
+                    String::from_utf8_lossy(raw)
+                );
+            }
+        }
+
+        #[test]
+        fn parse_errors_map_to_limit_responses() {
+            let table = [
+                (
+                    ParseError::MalformedStartLine,
+                    400,
+                    "The Header is invalid (first line)",
+                ),
+                (ParseError::UnknownMethod, 400, "Invalid Method"),
+                (
+                    ParseError::InvalidContentLength,
+                    400,
+                    "Invalid Content-Length",
+                ),
+                (ParseError::InvalidChunkedBody, 400, "Invalid chunked body"),
+                (
+                    ParseError::UnsupportedTransferEncoding,
+                    501,
+                    "Unsupported Transfer-Encoding",
+                ),
+                (
+                    ParseError::HeadersTooLarge,
+                    431,
+                    "Header fields exceed 16384 bytes",
+                ),
+                (
+                    ParseError::UriTooLong,
+                    414,
+                    "Request URI exceeds 8192 bytes",
+                ),
+                (
+                    ParseError::BodyTooLarge(10),
+                    413,
+                    "Request body exceeds 10 bytes",
+                ),
+                (ParseError::Http2Preface, 505, "HTTP/2 is not supported"),
+            ];
+            for (error, status, message) in table {
+                assert_eq!(
+                    error.error_response(),
+                    Some(ErrorResponse::new(status, message))
+                );
+                let text = response_text(ErrorResponse::new(status, message).into_response(false));
+                assert!(
+                    text.starts_with(&format!("HTTP/1.1 {} ", status)),
+                    "{}",
+                    text
+                );
+                assert!(text.ends_with(&format!("\r\n\r\n{}", message)), "{}", text);
+                let text = response_text(ErrorResponse::new(status, message).into_response(true));
+                assert!(
+                    text.ends_with(&format!("\r\n\r\n{{\"error\": \"{}\"}}", message)),
+                    "{}",
+                    text
+                );
+            }
+            assert_eq!(ParseError::Incomplete.error_response(), None);
+        }
+
+        #[test]
+        fn oversized_content_length_is_413_before_the_body() {
+            let address = spawn(|app| {
+                app.max_body_size(Some(10));
+                app.post("/upload".to_string(), |_, response| response.status(200));
+            });
+            // only the headers are sent , the answer must not wait for the announced body
+            let raw = b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 1000000\r\n\r\n";
+            let answer = exchange(address, raw);
+            assert!(answer.starts_with("HTTP/1.1 413 "), "{}", answer);
+            assert!(answer.contains("Connection: close\r\n"));
+        }
+
+        #[test]
+        fn connections_over_the_per_ip_limit_get_503() {
+            let address = spawn(|app| {
+                app.max_connections_per_ip(3);
+                app.get("/".to_string(), |_, response| response.status(200));
+            });
+            let mut open = Vec::new();
+            for _ in 0..3 {
+                let mut stream = TcpStream::connect(address).unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                stream
+                    .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+                    .unwrap();
+                let mut answer = [0u8; 15];
+                stream.read_exact(&mut answer).unwrap();
+                assert_eq!(&answer, b"HTTP/1.1 200 OK");
+                open.push(stream);
+            }
+            let raw = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            assert!(answer.starts_with("HTTP/1.1 503 "), "{}", answer);
+            assert!(answer.contains("More than 3 connections from 127.0.0.1"));
+
+            // a closed connection gives its slot back
+            drop(open.pop());
+            thread::sleep(Duration::from_millis(100));
+            assert!(exchange(address, raw).starts_with("HTTP/1.1 200 "));
+        }
+
+        #[test]
+        fn idle_connections_are_closed() {
+            let address = spawn(|app| {
+                app.max_keepalive_idle(Duration::from_millis(200));
+                app.get("/".to_string(), |_, response| {
+                    response.status(200).text("hi".to_string())
+                });
+            });
+            let started = Instant::now();
+            let answer = exchange(address, b"GET / HTTP/1.1\r\nHost: x\r\n\r\n");
+            assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(answer.contains("Connection: keep-alive\r\n"));
+            assert!(answer.ends_with("\r\n\r\nhi"));
+            assert!(started.elapsed() >= Duration::from_millis(200));
+        }
+
+        #[test]
+        fn slow_requests_get_408() {
+            let address = spawn(|app| {
+                app.request_timeout(Duration::from_millis(200));
+                app.get("/".to_string(), |_, response| response.status(200));
+            });
+            let answer = exchange(address, b"GET / HTTP/1.1\r\nHost");
+            assert!(answer.starts_with("HTTP/1.1 408 "), "{}", answer);
+            assert!(answer.ends_with("Request took too long to arrive"));
+        }
+
+        #[test]
+        fn required_bodies() {
+            let address = spawn(|app| {
+                app.post("/users/:id".to_string(), |_, response| response.status(201));
+                app.put("/users/:id".to_string(), |_, response| response.status(200));
+                app.with_request_body_required(Method::POST, "/users/:id");
+            });
+            let table: [(&[u8], &str); 5] = [
+                (b"POST /users/1 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n", "411"),
+                (b"POST /users/1 HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", "400"),
+                (
+                    b"POST /users/1 HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n0\r\n\r\n",
+                    "400",
+                ),
+                (b"POST /users/1 HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}", "201"),
+                (b"PUT /users/1 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n", "200"),
+            ];
+            for (raw, status) in table {
+                let answer = exchange(address, raw);
+                assert!(
+                    answer.starts_with(&format!("HTTP/1.1 {} ", status)),
+                    "{} {}",
+                    status,
+                    answer
+                );
+            }
+        }
+
+        #[test]
+        fn is_ajax() {
+            let (request, _) =
+                Request::parse(b"GET / HTTP/1.1\r\nX-Requested-With: xmlhttprequest\r\n\r\n")
+                    .unwrap();
+            assert!(request.is_ajax());
+            let (request, _) =
+                Request::parse(b"GET / HTTP/1.1\r\nX-Requested-With: fetch\r\n\r\n").unwrap();
+            assert!(!request.is_ajax());
+            let (request, _) = Request::parse(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            assert!(!request.is_ajax());
+        }
+
+        #[test]
+        fn default_index_page() {
+            let get = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let address = spawn(|app| app.default_index(true));
+            let answer = exchange(address, get);
+            assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(answer.ends_with("<h1>express-rs is running</h1>"));
+            let post =
+                b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            assert!(exchange(address, post).starts_with("HTTP/1.1 404 "));
+
+            // a route for / wins , and without the option / is a 404
+            let address = spawn(|app| {
+                app.default_index(true);
+                app.get("/".to_string(), |_, response| {
+                    response.status(200).text("home".to_string())
+                });
+            });
+            assert!(exchange(address, get).ends_with("\r\n\r\nhome"));
+            let address = spawn(|_| {});
+            assert!(exchange(address, get).starts_with("HTTP/1.1 404 "));
+        }
+
+        #[test]
+        fn form_bodies_are_decoded_and_keep_their_bytes() {
+            let raw = b"POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 30\r\n\r\nname=J%C3%A9r%C3%B4me+D&city=a";
+            let (request, _) = Request::parse(raw).unwrap();
+            let body = request.body();
+            let body = body.as_ref().unwrap();
+            assert_eq!(body.len(), 30);
+            assert!(!body.is_empty());
+            match body {
+                Body::FormData(form) => {
+                    assert_eq!(form.get("name").unwrap(), "Jérôme D");
+                    assert_eq!(form.get("city").unwrap(), "a");
+                    assert_eq!(form.raw(), b"name=J%C3%A9r%C3%B4me+D&city=a");
+                }
+                body => panic!("not a form: {:?}", body),
+            }
+
+            let (request, _) = Request::parse(b"POST / HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\nh\xC3\xA9").unwrap();
+            assert_eq!(request.body().as_ref().unwrap().len(), 3);
+        }
+
+        #[test]
+        fn vary_lists_each_header_once() {
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .vary("Accept")
+                    .vary(" accept ")
+                    .vary("Accept-Language")
+                    .vary("ACCEPT-LANGUAGE"),
+            );
+            assert_eq!(text.matches("Vary:").count(), 1);
+            assert!(text.contains("\r\nVary: Accept, Accept-Language\r\n"));
+        }
+
+        #[test]
+        fn no_cache_sets_the_three_headers() {
+            let text = response_text(Response::default().status(200).no_cache());
+            assert!(text.contains("\r\nCache-Control: no-store, no-cache, must-revalidate\r\n"));
+            assert!(text.contains("\r\nPragma: no-cache\r\n"));
+            assert!(text.contains("\r\nExpires: 0\r\n"));
+        }
+
+        #[test]
+        fn strict_headers_reject_invalid_utf8() {
+            let raw = b"GET / HTTP/1.1\r\nHost: x\r\nX-Name: caf\xFF\r\nConnection: close\r\n\r\n";
+            let route = |app: &mut Application| {
+                app.get("/".to_string(), |request, response| {
+                    let name = request.find_header("X-Name").cloned().unwrap_or_default();
+                    response.status(200).text(name)
+                });
+            };
+            let address = spawn(move |app| {
+                app.strict_headers(true);
+                route(app);
+            });
+            let answer = exchange(address, raw);
+            assert!(answer.starts_with("HTTP/1.1 400 "), "{}", answer);
+            assert!(answer.ends_with("Header fields are not valid UTF-8"));
+
+            // without strict mode the invalid byte is replaced
+            let address = spawn(route);
+            assert!(exchange(address, raw).ends_with("\r\n\r\ncaf\u{FFFD}"));
+        }
+
+        #[test]
+        fn server_timing_reports_the_handler_duration() {
+            let handler = |_: &Request, response: Response| {
+                thread::sleep(Duration::from_millis(20));
+                response.status(200)
+            };
+            let raw = b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let address = spawn(move |app| {
+                app.server_timing(true);
+                app.get("/".to_string(), handler);
+            });
+            let answer = exchange(address, raw);
+            let duration: f64 = answer
+                .split("\r\n")
+                .find_map(|line| line.strip_prefix("Server-Timing: app;dur="))
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!((20.0..5000.0).contains(&duration), "{}", duration);
+
+            let address = spawn(move |app| app.get("/".to_string(), handler));
+            assert!(!exchange(address, raw).contains("Server-Timing"));
+        }
+
+        #[test]
+        fn form_fields_keep_their_order() {
+            let form = Form::parse(b"b=2&a=1&b=3".to_vec());
+            let fields: Vec<(&str, &str)> = form.iter().collect();
+            assert_eq!(fields, [("b", "2"), ("a", "1"), ("b", "3")]);
+            assert_eq!(form.get("b").unwrap(), "2");
+            assert_eq!(form.get_all("b"), ["2", "3"]);
+            assert_eq!(form.len(), 3);
+        }
+
+        #[test]
+        fn connection_request_index_counts_per_connection() {
+            let address = spawn(|app| {
+                app.get("/".to_string(), |request, response| {
+                    response
+                        .status(200)
+                        .text(format!("[{}]", request.connection_request_index()))
+                });
+            });
+            let raw = b"GET / HTTP/1.1\r\nHost: x\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            let first = answer.find("[0]").unwrap();
+            let second = answer.find("[1]").unwrap();
+            assert!(first < second);
+            // a new connection starts again at 0
+            assert!(exchange(address, raw).contains("[0]"));
+        }
+
+        #[test]
+        fn connection_stays_usable_after_a_bodyless_status() {
+            let address = spawn(|app| {
+                // the body set by the handler must not be sent with a 304 or a 204
+                app.get("/cached".to_string(), |_, response| {
+                    response.status(304).text("stale".to_string())
+                });
+                app.get("/empty".to_string(), |_, response| {
+                    response.status(204).text("nothing".to_string())
+                });
+                app.get("/next".to_string(), |_, response| {
+                    response.status(200).text("next".to_string())
+                });
+            });
+            let raw = b"GET /cached HTTP/1.1\r\nHost: x\r\n\r\n\
+                GET /empty HTTP/1.1\r\nHost: x\r\n\r\n\
+                GET /next HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            let responses: Vec<&str> = answer.split("HTTP/1.1 ").skip(1).collect();
+            assert_eq!(responses.len(), 3, "{}", answer);
+            assert!(responses[0].starts_with("304 ") && responses[0].ends_with("\r\n\r\n"));
+            assert!(responses[1].starts_with("204 ") && responses[1].ends_with("\r\n\r\n"));
+            assert!(
+                !responses[0].contains("Content-Length")
+                    && !responses[1].contains("Content-Length")
+            );
+            assert!(responses[0].contains("Connection: keep-alive\r\n"));
+            assert!(responses[2].starts_with("200 ") && responses[2].ends_with("\r\n\r\nnext"));
+        }
+
+        #[test]
+        fn other_methods_keep_their_casing() {
+            let (request, _) = Request::parse(b"MkCol /dav HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(request.method_str(), "MkCol");
+            assert_eq!(request.method, Method::Other("MkCol".to_string()));
+            assert_ne!(request.method, Method::Other("MKCOL".to_string()));
+            // the standard methods are case sensitive too
+            let (request, _) = Request::parse(b"get / HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(request.method_str(), "get");
+            assert_ne!(request.method, Method::GET);
+
+            let address = spawn(|app| {
+                app.handle(
+                    Method::Other("MkCol".to_string()),
+                    "/dav".to_string(),
+                    |request: &Request, response: Response| {
+                        response.status(201).text(request.method_str().to_string())
+                    },
+                );
+            });
+            let answer = exchange(
+                address,
+                b"MkCol /dav HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 201 ") && answer.ends_with("\r\n\r\nMkCol"));
+            let answer = exchange(
+                address,
+                b"MKCOL /dav HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 405 "), "{}", answer);
+        }
+
+        #[test]
+        fn parse_range_table() {
+            let table = [
+                ("", None),
+                ("Range: bytes=0-99\r\n", Some(vec![(0, 99)])),
+                ("Range: bytes=900-\r\n", Some(vec![(900, 999)])),
+                ("Range: bytes=-100\r\n", Some(vec![(900, 999)])),
+                ("Range: bytes=-5000\r\n", Some(vec![(0, 999)])),
+                ("Range: bytes=950-2000\r\n", Some(vec![(950, 999)])),
+                ("Range: bytes=0-0, 10-19\r\n", Some(vec![(0, 0), (10, 19)])),
+                ("Range: bytes=1000-1100\r\n", Some(vec![])),
+                ("Range: bytes=50-10\r\n", None),
+                ("Range: items=0-10\r\n", None),
+                ("Range: bytes=abc\r\n", None),
+            ];
+            for (header, expected) in table {
+                let raw = format!("GET / HTTP/1.1\r\n{}\r\n", header);
+                let (request, _) = Request::parse(raw.as_bytes()).unwrap();
+                assert_eq!(request.parse_range(1000), expected, "{}", header);
+            }
+        }
+
+        #[test]
+        fn http2_preface_gets_505() {
+            let address = spawn(|app| app.get("/".to_string(), |_, response| response.status(200)));
+            let answer = exchange(address, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+            assert!(answer.starts_with("HTTP/1.1 505 "), "{}", answer);
+            assert!(answer.ends_with("HTTP/2 is not supported"));
+        }
+
+        #[test]
+        fn take_body_moves_the_body_out() {
+            let (request, _) = Request::parse(b"POST / HTTP/1.1\r\nContent-Type: application/octet-stream\r\nContent-Length: 3\r\n\r\n\x01\x02\x03").unwrap();
+            match request.take_body() {
+                Some(Body::Binary(bytes)) => assert_eq!(bytes, [1, 2, 3]),
+                body => panic!("not a binary body: {:?}", body),
+            }
+            assert!(request.take_body().is_none());
+            assert!(request.body().is_none());
+        }
+
+        #[test]
+        fn listen_many_serves_every_address() {
+            let free_port = || {
+                TcpListener::bind("127.0.0.1:0")
+                    .unwrap()
+                    .local_addr()
+                    .unwrap()
+            };
+            let addresses = [free_port(), free_port()];
+            let mut app = Application::new();
+            app.quiet(true);
+            app.get("/".to_string(), |request, response| {
+                response
+                    .status(200)
+                    .text(request.host().unwrap_or_default().to_string())
+            });
+            let handle = app.shutdown_handle();
+            let server = thread::spawn(move || {
+                let addresses = addresses.map(|address| address.to_string());
+                app.listen_many(&[&addresses[0], &addresses[1]]).unwrap();
+            });
+            for address in addresses {
+                let mut connected = TcpStream::connect(address);
+                for _ in 0..100 {
+                    if connected.is_ok() {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                    connected = TcpStream::connect(address);
+                }
+                drop(connected.unwrap());
+                let raw = format!(
+                    "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+                    address
+                );
+                let answer = exchange(address, raw.as_bytes());
+                assert!(answer.starts_with("HTTP/1.1 200 "), "{}", answer);
+                assert!(answer.ends_with(&address.to_string()));
+            }
+            handle.shutdown();
+            server.join().unwrap();
+
+            let mut app = Application::new();
+            let error = app.listen_many(&[]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        }
+
+        #[test]
+        fn connection_defaults_per_http_version() {
+            let address = spawn(|app| app.get("/".to_string(), |_, response| response.status(200)));
+            let table = [
+                // closing is the default of HTTP/1.0 , it isn't announced
+                ("HTTP/1.0", "", None, 1),
+                (
+                    "HTTP/1.0",
+                    "Connection: keep-alive\r\n",
+                    Some("keep-alive"),
+                    2,
+                ),
+                ("HTTP/1.1", "", Some("keep-alive"), 2),
+                ("HTTP/1.1", "Connection: close\r\n", Some("close"), 1),
+            ];
+            for (version, header, connection, responses) in table {
+                let first = format!("GET / {}\r\nHost: x\r\n{}\r\n", version, header);
+                let raw = format!(
+                    "{}GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    first
+                );
+                let answer = exchange(address, raw.as_bytes());
+                assert_eq!(
+                    answer.matches(" 200 OK\r\n").count(),
+                    responses,
+                    "{}",
+                    first
+                );
+                let first_response = answer.split("\r\n\r\n").next().unwrap();
+                match connection {
+                    Some(connection) => {
+                        let header = format!("\r\nConnection: {}", connection);
+                        assert!(first_response.contains(&header), "{}", answer);
+                    }
+                    None => assert!(!first_response.contains("Connection:"), "{}", answer),
+                }
+            }
+        }
+
+        #[test]
+        fn keep_alive_header_advertises_the_configured_limits() {
+            let address = spawn(|app| {
+                app.keep_alive_timeout(Duration::from_secs(7));
+                app.keep_alive_max_requests(3);
+                app.get("/".to_string(), |_, response| response.status(200));
+            });
+            let request = "GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+            let answer = exchange(address, request.repeat(3).as_bytes());
+            let heads: Vec<&str> = answer
+                .split("HTTP/1.1 ")
+                .filter(|head| !head.is_empty())
+                .collect();
+            assert_eq!(heads.len(), 3, "{}", answer);
+            assert!(heads[0].contains("\r\nKeep-Alive: timeout=7, max=2\r\n"));
+            assert!(heads[1].contains("\r\nKeep-Alive: timeout=7, max=1\r\n"));
+            assert!(heads[2].contains("\r\nConnection: close\r\n"));
+            assert!(!heads[2].contains("Keep-Alive:"));
+        }
+
+        #[test]
+        fn duplicate_route_policies() {
+            let address = spawn(|app| {
+                app.get("/a".to_string(), |_, response| {
+                    response.status(200).text("first".to_string())
+                });
+                app.get("/a".to_string(), |_, response| {
+                    response.status(200).text("second".to_string())
+                });
+                app.get("/users/:id".to_string(), |_, response| {
+                    response.status(200).text("first".to_string())
+                });
+                app.get("/users/:name".to_string(), |_, response| {
+                    response.status(200).text("second".to_string())
+                });
+            });
+            for path in ["/a", "/users/1"] {
+                let raw = format!(
+                    "GET {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    path
+                );
+                assert!(
+                    exchange(address, raw.as_bytes()).ends_with("\r\n\r\nsecond"),
+                    "{}",
+                    path
+                );
+            }
+
+            for (first, second) in [("/a", "/a"), ("/users/:id", "/users/:name")] {
+                let mut app = Application::new();
+                app.on_duplicate_route(DuplicatePolicy::Panic);
+                app.get(first.to_string(), |_, response| response);
+                app.post(second.to_string(), |_, response| response);
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    app.get(second.to_string(), |_, response| response);
+                }));
+                assert!(result.is_err(), "{}", second);
+            }
+        }
+
+        #[test]
+        fn fresh_and_stale() {
+            let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            let before = http_date(modified - Duration::from_secs(10));
+            let at = http_date(modified);
+            let table = [
+                (String::new(), Some("v1"), true, false),
+                (
+                    "If-None-Match: \"v1\"\r\n".to_string(),
+                    Some("v1"),
+                    false,
+                    true,
+                ),
+                (
+                    "If-None-Match: W/\"v1\"\r\n".to_string(),
+                    Some("\"v1\""),
+                    false,
+                    true,
+                ),
+                (
+                    "If-None-Match: \"v0\", \"v1\"\r\n".to_string(),
+                    Some("v1"),
+                    false,
+                    true,
+                ),
+                ("If-None-Match: *\r\n".to_string(), Some("v1"), false, true),
+                (
+                    "If-None-Match: \"v2\"\r\n".to_string(),
+                    Some("v1"),
+                    false,
+                    false,
+                ),
+                ("If-None-Match: \"v1\"\r\n".to_string(), None, false, false),
+                (
+                    "If-None-Match: \"v1\"\r\nCache-Control: no-cache\r\n".to_string(),
+                    Some("v1"),
+                    false,
+                    false,
+                ),
+                (format!("If-Modified-Since: {}\r\n", at), None, true, true),
+                (
+                    format!("If-Modified-Since: {}\r\n", before),
+                    None,
+                    true,
+                    false,
+                ),
+                // If-None-Match wins over If-Modified-Since
+                (
+                    format!("If-None-Match: \"v2\"\r\nIf-Modified-Since: {}\r\n", at),
+                    Some("v1"),
+                    true,
+                    false,
+                ),
+            ];
+            for (headers, etag, with_date, fresh) in table {
+                let raw = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+                let (request, _) = Request::parse(raw.as_bytes()).unwrap();
+                let last_modified = if with_date { Some(modified) } else { None };
+                assert_eq!(request.fresh(etag, last_modified), fresh, "{}", headers);
+                assert_eq!(request.stale(etag, last_modified), !fresh, "{}", headers);
+            }
+        }
+
+        #[test]
+        fn stream_json_array_of_many_items() {
+            let address = spawn(|app| {
+                app.get("/items".to_string(), |_, response| {
+                    let items = (0..10_000).map(|index| format!("{{\"id\":{}}}", index));
+                    response.status(200).stream_json_array(items)
+                });
+            });
+            let answer = exchange(
+                address,
+                b"GET /items HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+            assert!(head.contains("\r\nTransfer-Encoding: chunked"));
+            assert!(head.contains("\r\nContent-Type: application/json"));
+            let body = String::from_utf8(chunked::decode(body.as_bytes()).unwrap()).unwrap();
+            assert!(is_valid_json(&body));
+            assert_eq!(body.matches("\"id\"").count(), 10_000);
+            assert!(body.starts_with("[{\"id\":0},{\"id\":1},"));
+            assert!(body.ends_with(",{\"id\":9999}]"));
+        }
+
+        #[test]
+        fn prefer_chunked_body_decodes_to_the_original() {
+            let body = "x".repeat(5000);
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .text(body.clone())
+                    .prefer_chunked(true),
+            );
+            let (head, chunks) = text.split_once("\r\n\r\n").unwrap();
+            assert!(head.contains("\r\nTransfer-Encoding: chunked"));
+            assert!(!head.contains("Content-Length"));
+            assert_eq!(chunked::decode(chunks.as_bytes()).unwrap(), body.as_bytes());
+
+            // no_transform keeps the Content-Length
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .text(body)
+                    .prefer_chunked(true)
+                    .no_transform(),
+            );
+            assert!(text.contains("\r\nContent-Length: 5000\r\n"));
+            assert!(!text.contains("Transfer-Encoding"));
+        }
+
+        #[test]
+        fn head_is_answered_by_the_get_route_without_body() {
+            let address = spawn(|app| {
+                app.get("/page".to_string(), |_, response| {
+                    response.status(200).text("hello".to_string())
+                });
+                app.get("/own".to_string(), |_, response| {
+                    response.status(200).text("get".to_string())
+                });
+                app.handle(
+                    Method::HEAD,
+                    "/own".to_string(),
+                    |_: &Request, response: Response| response.status(200).header("X-Head", "1"),
+                );
+            });
+            let raw = b"HEAD /page HTTP/1.1\r\nHost: x\r\n\r\n\
+                HEAD /own HTTP/1.1\r\nHost: x\r\n\r\n\
+                GET /page HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            let responses: Vec<&str> = answer.split("HTTP/1.1 ").skip(1).collect();
+            assert_eq!(responses.len(), 3, "{}", answer);
+            assert!(responses[0].contains("\r\nContent-Length: 5\r\n"));
+            assert!(responses[0].ends_with("\r\n\r\n"));
+            assert!(
+                responses[1].contains("\r\nX-Head: 1\r\n") && responses[1].ends_with("\r\n\r\n")
+            );
+            assert!(responses[2].ends_with("\r\n\r\nhello"));
+        }
+
+        #[test]
+        fn matching_etag_gets_304() {
+            let address = spawn(|app| {
+                app.get("/logo".to_string(), |_, response| {
+                    response
+                        .status(200)
+                        .bytes_with_etag(vec![1, 2, 3], "image/png")
+                });
+            });
+            let answer = exchange(
+                address,
+                b"GET /logo HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 200 "));
+            let etag = answer
+                .split("\r\n")
+                .find_map(|line| line.strip_prefix("ETag: "))
+                .unwrap()
+                .to_string();
+
+            let raw = format!(
+                "GET /logo HTTP/1.1\r\nHost: x\r\nIf-None-Match: {}\r\nConnection: close\r\n\r\n",
+                etag
+            );
+            let answer = exchange(address, raw.as_bytes());
+            assert!(answer.starts_with("HTTP/1.1 304 "), "{}", answer);
+            assert!(answer.contains(&format!("\r\nETag: {}\r\n", etag)));
+            assert!(answer.ends_with("\r\n\r\n") && !answer.contains("Content-Length"));
+
+            let raw = b"GET /logo HTTP/1.1\r\nHost: x\r\nIf-None-Match: \"other\"\r\nConnection: close\r\n\r\n";
+            let answer = exchange(address, raw);
+            assert!(
+                answer.starts_with("HTTP/1.1 200 ") && answer.ends_with("\r\n\r\n\x01\x02\x03")
+            );
+        }
+
+        #[test]
+        fn wildcard_segments() {
+            let mut app = Application::new();
+            app.get("/files/:bucket/*path".to_string(), |_, response| response);
+            let result = app
+                .match_route(&Method::GET, "/files/docs/a%20b/c.txt")
+                .unwrap();
+            assert_eq!(result.params["bucket"], "docs");
+            assert_eq!(result.params["path"], "a b/c.txt");
+            assert!(app.match_route(&Method::GET, "/files/docs").is_none());
+            assert!(app.match_route(&Method::GET, "/other/docs/a").is_none());
+
+            assert!(route_matches("/files/*path", "/files/a/b"));
+            assert!(!route_matches("/files/*path", "/files"));
+            let result = panic::catch_unwind(|| route_segments("/files/*path/edit"));
+            assert!(result.is_err());
+        }
+
+        #[cfg(feature = "tokio")]
+        #[test]
+        fn async_application_answers_405_500_and_shuts_down() {
+            let mut app = AsyncApplication::new();
+            app.get("/user/:id".to_string(), |request, response| {
+                let id = request.get_param("id").unwrap();
+                async move { response.status(200).text(format!("user {}", id)) }
+            });
+            app.get(
+                "/sync-panic".to_string(),
+                |_, _| -> std::future::Ready<Response> { panic!("before the future") },
+            );
+            app.get("/async-panic".to_string(), |_, _| async {
+                panic!("while awaiting");
+            });
+            let handle = app.shutdown_handle();
+            let (address, server) = spawn_async(app);
+
+            let answer = exchange(
+                address,
+                b"DELETE /user/7 HTTP/1.1\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+            assert!(answer.contains("Allow: GET, HEAD\r\n"));
+            let answer = exchange(
+                address,
+                b"HEAD /user/7 HTTP/1.1\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(answer.ends_with("\r\n\r\n"));
+            for path in ["/sync-panic", "/async-panic"] {
+                let raw = format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", path);
+                let answer = exchange(address, raw.as_bytes());
+                assert!(answer.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+            }
+            let answer = exchange(
+                address,
+                b"GET /user/8 HTTP/1.1\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.ends_with("user 8"));
+
+            handle.shutdown();
+            server.join().unwrap();
+            assert!(TcpStream::connect(address).is_err());
+        }
+
+        #[test]
+        fn listen_with_ready_reports_a_taken_port() {
+            let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = taken.local_addr().unwrap().port() as i32;
+            let (ready, started) = mpsc::channel();
+            let mut app = Application::new();
+            app.quiet(true);
+            assert!(app.listen_with_ready(port, ready).is_err());
+            assert!(started.recv().is_err());
+        }
+
+        #[test]
+        fn duplicate_route_warning_goes_to_the_log_sink() {
+            #[derive(Clone, Default)]
+            struct Captured(Arc<Mutex<Vec<u8>>>);
+
+            impl Write for Captured {
+                fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+                    return self.0.lock().unwrap().write(bytes);
+                }
+                fn flush(&mut self) -> std::io::Result<()> {
+                    return Ok(());
+                }
+            }
+
+            for quiet in [false, true] {
+                let captured = Captured::default();
+                let mut app = Application::new();
+                app.log_sink(captured.clone());
+                app.quiet(quiet);
+                app.get("/a".to_string(), |_, response| response);
+                app.get("/a".to_string(), |_, response| response);
+                let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+                if quiet {
+                    assert!(logged.is_empty());
+                } else {
+                    assert_eq!(
+                        logged,
+                        "warning: route GET /a is registered twice, the last handler is used\n"
+                    );
+                }
+            }
+
+            let result = catch_panic(true, || panic!("hidden"));
+            assert!(result.is_err());
+            assert!(!QUIET_PANICS.with(|quiet| quiet.get()));
+        }
+
+        #[test]
+        fn error_bodies_in_both_formats() {
+            let response = Response {
+                json_errors: true,
+                ..Default::default()
+            };
+            let text = response_text(response.error(422, "bad \"name\""));
+            assert!(text.starts_with("HTTP/1.1 422 Unprocessable Content\r\n"));
+            assert!(text.contains("Content-Type: application/json\r\n"));
+            assert!(text.ends_with("\r\n\r\n{\"error\": \"bad \\\"name\\\"\"}"));
+
+            let text = response_text(Response::default().error(422, "invalid"));
+            assert!(text.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+            assert!(text.ends_with("\r\n\r\ninvalid"));
+        }
+
+        #[cfg(feature = "tokio")]
+        #[test]
+        fn async_application_closes_idle_and_slow_connections() {
+            let mut app = AsyncApplication::new();
+            app.keep_alive_timeout(Duration::from_millis(200));
+            app.request_timeout(Duration::from_millis(200));
+            app.get("/".to_string(), |_, response| async move {
+                response.status(200)
+            });
+            let handle = app.shutdown_handle();
+            let (address, server) = spawn_async(app);
+
+            // nothing sent , the connection is closed without an answer
+            assert_eq!(exchange(address, b""), "");
+            // half a header , the request is answered with a 408
+            let answer = exchange(address, b"GET / HTTP/1.1\r\nHost");
+            assert!(answer.starts_with("HTTP/1.1 408 "), "{}", answer);
+            assert!(answer.contains("Connection: close\r\n"));
+            assert!(answer.ends_with("Request took too long to arrive"));
+
+            handle.shutdown();
+            server.join().unwrap();
+        }
+
+        #[test]
+        fn pipe_forwards_upstream_responses() {
+            let upstream: &[u8] = b"HTTP/1.1 502 Bad Gateway\r\nX-Upstream: cache\r\nContent-Type: text/plain\r\nContent-Length: 4\r\n\r\ndown";
+            let text = response_text(Response::default().pipe(upstream).unwrap());
+            assert_eq!(
+                text,
+                "HTTP/1.1 502 Bad Gateway\r\nContent-Length: 4\r\nContent-Type: text/plain\r\nX-Upstream: cache\r\n\r\ndown"
+            );
+
+            let upstream: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n3\r\n!!!\r\n0\r\n\r\n";
+            let text = response_text(Response::default().pipe(upstream).unwrap());
+            assert!(!text.contains("Transfer-Encoding"));
+            assert!(text.contains("Content-Length: 5\r\n"));
+            assert!(text.ends_with("\r\n\r\nhi!!!"));
+
+            // the body ends with the upstream connection
+            let upstream: &[u8] =
+                b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n<p>until the end</p>";
+            let text = response_text(Response::default().pipe(upstream).unwrap());
+            assert!(text.contains("Content-Length: 20\r\n"));
+            assert!(text.ends_with("\r\n\r\n<p>until the end</p>"));
+
+            // 204 and 304 have no body , the bytes after the headers aren't read
+            for status in ["204 No Content", "304 Not Modified"] {
+                let raw = format!("HTTP/1.1 {}\r\nETag: \"v1\"\r\n\r\nnext response", status);
+                let text = response_text(Response::default().pipe(raw.as_bytes()).unwrap());
+                assert_eq!(text, format!("HTTP/1.1 {}\r\nETag: \"v1\"\r\n\r\n", status));
+            }
+        }
+
+        #[test]
+        fn pipe_drops_hop_by_hop_headers() {
+            let upstream: &[u8] = b"HTTP/1.1 200 OK\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\nKeep-Alive: timeout=5\r\nProxy-Connection: close\r\nTE: trailers\r\nTrailer: Expires\r\nUpgrade: h2c\r\nX-End: 2\r\nContent-Type: text/plain\rX-Evil: 1\r\nContent-Length: 2\r\n\r\nok";
+            let text = response_text(Response::default().pipe(upstream).unwrap());
+            assert_eq!(
+                text,
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Type: text/plainX-Evil: 1\r\nX-End: 2\r\n\r\nok"
+            );
+        }
+
+        #[test]
+        fn response_without_body_is_empty_200() {
+            let text = response_text(Response::default().status(200));
+            assert_eq!(text, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        }
+
+        #[test]
+        fn content_type_is_sent_without_a_body() {
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .header("Content-Type", "text/csv"),
+            );
+            assert_eq!(
+                text,
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nContent-Type: text/csv\r\n\r\n"
+            );
+            let text = response_text(
+                Response::default()
+                    .status(204)
+                    .header("Content-Type", "text/csv"),
+            );
+            assert_eq!(text, "HTTP/1.1 204 No Content\r\n\r\n");
+        }
+
+        #[test]
+        fn strip_prefix_routes_the_rest_of_the_path() {
+            let address = spawn(|app| {
+                app.strip_prefix("/myapp/");
+                app.get("/users".to_string(), |request, response| {
+                    response.status(200).text(request.route.clone())
+                });
+            });
+            let answer = exchange(
+                address,
+                b"GET /myapp/users HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"), "{}", answer);
+            let answer = exchange(
+                address,
+                b"GET /users HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.starts_with("HTTP/1.1 404 "), "{}", answer);
+        }
+
+        #[test]
+        fn full_url_rebuilds_the_request_url() {
+            let (request, _) =
+                Request::parse(b"GET /search?q=1 HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            assert_eq!(request.scheme(), "http");
+            assert!(!request.is_secure());
+            assert_eq!(request.full_url(), "http://localhost/search?q=1");
+
+            let (request, _) = Request::parse(b"GET /search?q=1 HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(request.full_url(), "/search?q=1");
+        }
+
+        #[test]
+        fn handler_structs_and_closures_are_both_routes() {
+            struct Greeter {
+                greeting: String,
+            }
+
+            impl Handler for Greeter {
+                fn handle(&self, request: &Request, response: Response) -> Response {
+                    let name = request.get_param("name").unwrap();
+                    return response
+                        .status(200)
+                        .text(format!("{} {}", self.greeting, name));
+                }
+            }
+
+            let address = spawn(|app| {
+                let greeter = Greeter {
+                    greeting: "Hello".to_string(),
+                };
+                app.handle(Method::GET, "/hello/:name".to_string(), greeter);
+                app.get("/bye".to_string(), |_, response| {
+                    response.status(200).text("Bye".to_string())
+                });
+            });
+            let answer = exchange(
+                address,
+                b"GET /hello/ada HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.ends_with("\r\n\r\nHello ada"), "{}", answer);
+            let answer = exchange(
+                address,
+                b"GET /bye HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.ends_with("\r\n\r\nBye"), "{}", answer);
+        }
+
+        #[test]
+        fn header_or_falls_back_to_the_default() {
+            let (request, _) =
+                Request::parse(b"GET / HTTP/1.1\r\nAccept-Language: fr\r\n\r\n").unwrap();
+            assert_eq!(request.header_or("accept-language", "en"), "fr");
+            assert_eq!(request.header_or("X-Missing", "en"), "en");
+        }
+
+        #[test]
+        fn nosniff_is_sent_with_typed_responses() {
+            let address = spawn(|app| {
+                app.nosniff(true);
+                app.get("/typed".to_string(), |_, response| {
+                    response.status(200).text("hi".to_string())
+                });
+                app.get("/empty".to_string(), |_, response| response.status(204));
+            });
+            let answer = exchange(
+                address,
+                b"GET /typed HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(
+                answer.contains("\r\nX-Content-Type-Options: nosniff\r\n"),
+                "{}",
+                answer
+            );
+            let answer = exchange(
+                address,
+                b"GET /empty HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(!answer.contains("X-Content-Type-Options"), "{}", answer);
+        }
+
+        #[test]
+        fn before_route_rewrites_the_route() {
+            let address = spawn(|app| {
+                app.before_route(|request| {
+                    if request.route == "/old" {
+                        request.route = "/new".to_string();
+                    }
+                });
+                app.get("/new".to_string(), |_, response| {
+                    response.status(200).text("new".to_string())
+                });
+            });
+            for path in ["/old", "/new"] {
+                let raw = format!(
+                    "GET {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    path
+                );
+                let answer = exchange(address, raw.as_bytes());
+                assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"), "{}", answer);
+                assert!(answer.ends_with("\r\n\r\nnew"), "{}", answer);
+            }
+        }
+
+        #[test]
+        fn last_modified_and_etag_header_formats() {
+            let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+            let text = response_text(Response::default().status(200).last_modified(modified));
+            assert!(text.contains("\r\nLast-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+
+            let table = [
+                ("v1", "\"v1\""),
+                ("\"v1\"", "\"v1\""),
+                ("W/v1", "W/\"v1\""),
+                ("W/\"v1\"", "W/\"v1\""),
+            ];
+            for (tag, header) in table {
+                let text = response_text(Response::default().status(200).etag(tag));
+                assert!(
+                    text.contains(&format!("\r\nETag: {}\r\n", header)),
+                    "{}",
+                    tag
+                );
+            }
+        }
+
+        #[test]
+        fn render_substitutes_context_values() {
+            let mut context = HashMap::new();
+            context.insert("name".to_string(), "omar".to_string());
+            context.insert("city".to_string(), "Cairo".to_string());
+            let text = response_text(Response::default().status(200).render(
+                "<h1>Hello {{name}}</h1><p>{{ city }} {{missing}}</p>",
+                &context,
+            ));
+            assert!(text.contains("\r\nContent-Type: text/html"));
+            assert!(text.ends_with("\r\n\r\n<h1>Hello omar</h1><p>Cairo </p>"));
+        }
+
+        #[test]
+        fn render_escapes_markup_in_values() {
+            let mut context = HashMap::new();
+            context.insert(
+                "name".to_string(),
+                "<script>alert('x' & \"y\")</script>".to_string(),
+            );
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .render("<p>{{name}}</p>", &context),
+            );
+            assert!(text.ends_with(
+                "\r\n\r\n<p>&lt;script&gt;alert(&#39;x&#39; &amp; &quot;y&quot;)&lt;/script&gt;</p>"
+            ));
+        }
+
+        #[test]
+        fn param_and_search_param_counts() {
+            let address = spawn(|app| {
+                app.get("/a/:x/:y".to_string(), |request, response| {
+                    let counts =
+                        format!("{}/{}", request.param_count(), request.search_param_count());
+                    response.status(200).text(counts)
+                });
+            });
+            let answer = exchange(
+                address,
+                b"GET /a/1/2?p=1&q=2 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            );
+            assert!(answer.ends_with("\r\n\r\n2/2"), "{}", answer);
+        }
+
+        #[test]
+        fn form_value_and_form_values_read_the_body() {
+            let raw = b"POST /pizza HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 44\r\n\r\nsize=large&topping=cheese&topping=olives+%26";
+            let (request, _) = Request::parse(raw).unwrap();
+            assert_eq!(request.form_value("size"), Some("large".to_string()));
+            assert_eq!(request.form_value("crust"), None);
+            assert_eq!(request.form_values("size"), vec!["large"]);
+            assert_eq!(request.form_values("topping"), vec!["cheese", "olives &"]);
+            assert!(request.form_values("crust").is_empty());
+
+            let raw = b"POST /pizza HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 10\r\n\r\nsize=large";
+            let (request, _) = Request::parse(raw).unwrap();
+            assert_eq!(request.form_value("size"), None);
+            assert!(request.form_values("size").is_empty());
+        }
+
+        #[test]
+        fn mount_fn_sees_the_route_without_its_prefix() {
+            let address = spawn(|app| {
+                app.get("/admin/login".to_string(), |_, response| {
+                    response.status(200).text("login".to_string())
+                });
+                app.mount_fn("/admin", |request, response| {
+                    let method = request.method.as_str();
+                    response
+                        .status(200)
+                        .text(format!("{} {}", method, request.route))
+                });
+            });
+            let table = [
+                ("GET", "/admin/dashboard", "GET /dashboard"),
+                ("POST", "/admin/users/1", "POST /users/1"),
+                ("GET", "/admin/login", "login"),
+            ];
+            for (method, path, body) in table {
+                let raw = format!(
+                    "{} {} HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    method, path
+                );
+                let answer = exchange(address, raw.as_bytes());
+                assert!(answer.ends_with(&format!("\r\n\r\n{}", body)), "{}", answer);
+            }
+        }
+
+        #[test]
+        fn raw_responses_are_written_verbatim() {
+            let bytes =
+                b"HTTP/1.1 299 Custom\r\nX-Anything: goes\r\n\r\nbody without length".to_vec();
+            let text = response_text(
+                Response::default()
+                    .status(200)
+                    .header("X-Ignored", "1")
+                    .text("ignored".to_string())
+                    .raw(bytes.clone()),
+            );
+            assert_eq!(text.as_bytes(), &bytes[..]);
+        }
+
+        #[test]
+        fn host_and_hostname_with_and_without_port() {
+            let table = [
+                (
+                    "Host: example.com\r\n",
+                    Some("example.com"),
+                    Some("example.com"),
+                ),
+                (
+                    "Host: example.com:8080\r\n",
+                    Some("example.com:8080"),
+                    Some("example.com"),
+                ),
+                ("Host: [::1]:8080\r\n", Some("[::1]:8080"), Some("[::1]")),
+                (
+                    "Host: [2001:db8::1]\r\n",
+                    Some("[2001:db8::1]"),
+                    Some("[2001:db8::1]"),
+                ),
+                ("", None, None),
+            ];
+            for (header, host, hostname) in table {
+                let raw = format!("GET / HTTP/1.1\r\n{}\r\n", header);
+                let (request, _) = Request::parse(raw.as_bytes()).unwrap();
+                assert_eq!(request.host(), host, "{}", header);
+                assert_eq!(request.hostname(), hostname, "{}", header);
+            }
+        }
+
+        #[cfg(feature = "gzip")]
+        #[test]
+        fn no_transform_is_never_compressed() {
+            let address = spawn(|app| {
+                app.compression(Some(100));
+                app.get("/plain".to_string(), |_, response| {
+                    response.status(200).text("hello ".repeat(100))
+                });
+                app.get("/exact".to_string(), |_, response| {
+                    response
+                        .status(200)
+                        .text("hello ".repeat(100))
+                        .no_transform()
+                });
+            });
+            let request = |path: &str| {
+                format!(
+                    "GET {} HTTP/1.1\r\nHost: x\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+                    path
+                )
+            };
+            let answer = exchange(address, request("/plain").as_bytes());
+            assert!(
+                answer.contains("\r\nContent-Encoding: gzip\r\n"),
+                "{}",
+                answer
+            );
+
+            let answer = exchange(address, request("/exact").as_bytes());
+            assert!(!answer.contains("Content-Encoding"), "{}", answer);
+            assert!(answer.contains("\r\nCache-Control: no-transform\r\n"));
+            assert!(answer.contains("\r\nContent-Length: 600\r\n"));
+            assert!(answer.ends_with(&"hello ".repeat(100)));
+        }
+
+        #[test]
+        fn clear_cookie_sends_it_expired() {
+            let text = response_text(
+                Response::default()
+                    .status(204)
+                    .clear_cookie("session", "/")
+                    .clear_cookie("bad name", "/")
+                    .clear_cookie("theme", "/a;b"),
+            );
+            assert_eq!(text.matches("Set-Cookie:").count(), 1, "{}", text);
+            assert!(text.contains(
+                "\r\nSet-Cookie: session=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT\r\n"
+            ));
+        }
+
+        #[test]
+        fn static_dir_rejects_other_methods_like_every_405() {
+            for json_errors in [false, true] {
+                let address = spawn(move |app| {
+                    app.json_errors(json_errors);
+                    app.static_dir("/assets", env!("CARGO_MANIFEST_DIR"));
+                });
+                let answer = exchange(
+                    address,
+                    b"DELETE /assets/Cargo.toml HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                );
+                assert!(
+                    answer.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+                    "{}",
+                    answer
+                );
+                assert!(answer.contains("\r\nAllow: GET, HEAD\r\n"));
+                let body = if json_errors {
+                    "{\"error\": \"Method Not Allowed\"}"
+                } else {
+                    "Method Not Allowed"
+                };
+                assert!(answer.ends_with(&format!("\r\n\r\n{}", body)), "{}", answer);
+            }
+        }
+
+        #[test]
+        fn cookies_are_validated_and_sent_one_per_line() {
+            let text = response_text(
+                Response::default()
+                    .status(204)
+                    .cookie("a", "1", CookieOptions::default())
+                    .cookie("b", "\"2\"", CookieOptions::default())
+                    .cookie(
+                        "evil",
+                        "x; Domain=attacker.example",
+                        CookieOptions::default(),
+                    )
+                    .cookie("bad name", "1", CookieOptions::default())
+                    .cookie("c", "3\r\nX-Injected: 1", CookieOptions::default())
+                    .cookie(
+                        "d",
+                        "4",
+                        CookieOptions {
+                            path: Some("/; Domain=attacker.example".to_string()),
+                            ..Default::default()
+                        },
+                    )
+                    .clear_cookie("e", "/")
+                    .clear_cookie("f=g", "/"),
+            );
+            let cookies: Vec<&str> = text
+                .split("\r\n")
+                .filter(|line| line.starts_with("Set-Cookie: "))
+                .collect();
+            assert_eq!(cookies.len(), 3);
+            assert!(cookies[0].starts_with("Set-Cookie: a=1; Path=/"));
+            assert!(cookies[1].starts_with("Set-Cookie: b=\"2\"; Path=/"));
+            assert!(cookies[2].starts_with("Set-Cookie: e=; Path=/; Max-Age=0"));
+            assert!(!text.contains("attacker") && !text.contains("Injected"));
         }
     }
 }
@@ -1,3 +1,4 @@
+#![allow(clippy::needless_return)]
 
 fn main() {
     use express_rs::express;